@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CACHE_FILE_NAME: &str = "hashes.csv";
+
+/// # CacheKey
+/// Identifies a version of a file's contents.  If any part of the key changes, the cached hash is stale.
+///
+/// * `identity` - The device and inode of the file, or its path on platforms without inodes.
+/// * `size` - The size of the file in bytes.
+/// * `modified` - The last modified time of the file, in nanoseconds since the epoch.
+/// * `algorithm` - The hash algorithm, including any normalization applied.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub identity: String,
+    pub size: u64,
+    pub modified: i64,
+    pub algorithm: String,
+}
+
+impl CacheKey {
+    /// Build the key for a file by reading its metadata.
+    pub fn for_file(path: &str, algorithm: &str) -> Result<CacheKey, io::Error> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(CacheKey {
+            identity: file_identity(path, &metadata),
+            size: metadata.len(),
+            modified: modified_nanos(&metadata)?,
+            algorithm: algorithm.to_string(),
+        })
+    }
+}
+
+/// # CacheEntry
+/// A cached hash.
+///
+/// * `path` - The path the file had when it was hashed.
+/// * `hash` - The hash of the file.
+/// * `normalized` - The file had CRLF line endings normalized when it was hashed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheEntry {
+    pub path: String,
+    pub hash: String,
+    pub normalized: bool,
+}
+
+/// # HashCache
+/// On disk cache of file hashes, stored as a csv file.
+///
+/// * `path` - The path of the cache file.
+/// * `entries` - The cached hashes.
+pub struct HashCache {
+    path: PathBuf,
+    entries: HashMap<CacheKey, CacheEntry>,
+}
+
+impl HashCache {
+    /// Create an empty cache that will be saved to a file.
+    pub fn new(path: &Path) -> HashCache {
+        HashCache {
+            path: path.to_path_buf(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Load the cache from a file.  A missing file is an empty cache.
+    pub fn load(path: &Path) -> Result<HashCache, io::Error> {
+        let mut cache = HashCache::new(path);
+        if !path.exists() {
+            return Ok(cache);
+        }
+
+        let mut rdr = csv::Reader::from_path(path)?;
+        for record in rdr.records() {
+            let record = record?;
+            let field = |index: usize| record.get(index).unwrap_or_default();
+            let key = CacheKey {
+                identity: field(0).to_string(),
+                size: field(1).parse().map_err(io::Error::other)?,
+                modified: field(2).parse().map_err(io::Error::other)?,
+                algorithm: field(3).to_string(),
+            };
+            let entry = CacheEntry {
+                hash: field(4).to_string(),
+                normalized: field(5) == "true",
+                path: field(6).to_string(),
+            };
+            cache.entries.insert(key, entry);
+        }
+        Ok(cache)
+    }
+
+    /// Write the cache to its file, replacing the file atomically.
+    pub fn save(&self) -> Result<(), io::Error> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp_path = self.path.with_extension("csv.tmp");
+        {
+            let mut wtr = csv::Writer::from_path(&temp_path)?;
+            wtr.write_record([
+                "Identity",
+                "Size",
+                "Modified",
+                "Algorithm",
+                "Hash",
+                "Normalized",
+                "Path",
+            ])?;
+            for (key, entry) in self.entries.iter() {
+                wtr.write_record(&[
+                    key.identity.clone(),
+                    key.size.to_string(),
+                    key.modified.to_string(),
+                    key.algorithm.clone(),
+                    entry.hash.clone(),
+                    entry.normalized.to_string(),
+                    entry.path.clone(),
+                ])?;
+            }
+            wtr.flush()?;
+        }
+        std::fs::rename(&temp_path, &self.path)
+    }
+
+    /// The path of the cache file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Look up the cached hash for a key.
+    pub fn get(&self, key: &CacheKey) -> Option<&CacheEntry> {
+        self.entries.get(key)
+    }
+
+    /// Add or replace the cached hash for a key.
+    pub fn insert(&mut self, key: CacheKey, entry: CacheEntry) {
+        self.entries.insert(key, entry);
+    }
+
+    /// The number of cached hashes.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The number of cached hashes for each algorithm.
+    pub fn count_by_algorithm(&self) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for key in self.entries.keys() {
+            *counts.entry(key.algorithm.clone()).or_default() += 1;
+        }
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort();
+        counts
+    }
+
+    /// Remove entries for files that no longer exist or have changed since they were hashed.
+    /// Returns the number of entries removed.
+    pub fn prune(&mut self) -> usize {
+        let count = self.entries.len();
+        self.entries.retain(
+            |key, entry| match CacheKey::for_file(&entry.path, &key.algorithm) {
+                Ok(current) => current == *key,
+                Err(_) => false,
+            },
+        );
+        count - self.entries.len()
+    }
+
+    /// Remove entries for files under a path, or every entry if no path is given.
+    /// Returns the number of entries removed.
+    pub fn invalidate(&mut self, prefix: Option<&str>) -> usize {
+        let count = self.entries.len();
+        match prefix {
+            Some(prefix) => self
+                .entries
+                .retain(|_, entry| !Path::new(&entry.path).starts_with(prefix)),
+            None => self.entries.clear(),
+        }
+        count - self.entries.len()
+    }
+}
+
+/// # default_cache_path
+/// The default location of the cache file.
+/// `$XDG_CACHE_HOME/dupefindr`, falling back to `~/.cache/dupefindr`, or `%LOCALAPPDATA%\dupefindr` on Windows.
+pub fn default_cache_path() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let base = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+    #[cfg(not(target_os = "windows"))]
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")));
+    base.map(|dir| dir.join("dupefindr").join(CACHE_FILE_NAME))
+}
+
+/// # file_identity
+/// The identity of a file: its device and inode where available, otherwise its path.
+fn file_identity(path: &str, metadata: &std::fs::Metadata) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let _ = path;
+        format!("{}:{}", metadata.dev(), metadata.ino())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        path.to_string()
+    }
+}
+
+/// # modified_nanos
+/// The last modified time of a file, in nanoseconds since the epoch.
+fn modified_nanos(metadata: &std::fs::Metadata) -> Result<i64, io::Error> {
+    let modified = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(io::Error::other)?;
+    Ok(modified.as_nanos() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let cache_path = temp_dir.path().join("cache").join(CACHE_FILE_NAME);
+        let file_path = temp_dir.path().join("file.txt");
+        std::fs::write(&file_path, b"contents").unwrap();
+        let file_path = file_path.to_str().unwrap();
+
+        let mut cache = HashCache::load(&cache_path).unwrap();
+        assert_eq!(cache.len(), 0);
+        let key = CacheKey::for_file(file_path, "md5").unwrap();
+        cache.insert(
+            key.clone(),
+            CacheEntry {
+                path: file_path.to_string(),
+                hash: "abc".to_string(),
+                normalized: false,
+            },
+        );
+        cache.save().unwrap();
+
+        let cache = HashCache::load(&cache_path).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&key).unwrap().hash, "abc");
+        // a different algorithm is a different key
+        let other_key = CacheKey::for_file(file_path, "sha256").unwrap();
+        assert!(cache.get(&other_key).is_none());
+        assert_eq!(cache.count_by_algorithm(), vec![("md5".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_cache_prune_and_invalidate() {
+        let temp_dir = tempdir().unwrap();
+        let cache_path = temp_dir.path().join(CACHE_FILE_NAME);
+        let mut cache = HashCache::load(&cache_path).unwrap();
+        for name in ["kept.txt", "changed.txt", "removed.txt"] {
+            let file_path = temp_dir.path().join(name);
+            std::fs::write(&file_path, name).unwrap();
+            let file_path = file_path.to_str().unwrap();
+            cache.insert(
+                CacheKey::for_file(file_path, "md5").unwrap(),
+                CacheEntry {
+                    path: file_path.to_string(),
+                    hash: name.to_string(),
+                    normalized: false,
+                },
+            );
+        }
+        std::fs::write(temp_dir.path().join("changed.txt"), "new contents").unwrap();
+        std::fs::remove_file(temp_dir.path().join("removed.txt")).unwrap();
+
+        assert_eq!(cache.prune(), 2);
+        assert_eq!(cache.len(), 1);
+
+        assert_eq!(cache.invalidate(Some("/some/other/path")), 0);
+        assert_eq!(cache.invalidate(temp_dir.path().to_str()), 1);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_cache_load_corrupt() {
+        let temp_dir = tempdir().unwrap();
+        let cache_path = temp_dir.path().join(CACHE_FILE_NAME);
+        std::fs::write(
+            &cache_path,
+            "Identity,Size,Modified,Algorithm,Hash,Normalized,Path\n1:2,notanumber,0,md5,abc,false,x\n",
+        )
+        .unwrap();
+        assert!(HashCache::load(&cache_path).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use serde::Deserialize;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// # Config
+/// Settings read from the config file, which apply to every run.
+///
+/// * `protect` - Paths or wildcards of files that are never modified.  Added to those given with --protect.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub protect: Vec<String>,
+}
+
+impl Config {
+    /// Read a config file.
+    pub fn load(path: &Path) -> Result<Config, io::Error> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+}
+
+/// # default_config_path
+/// The default location of the config file.
+/// `$XDG_CONFIG_HOME/dupefindr`, falling back to `~/.config/dupefindr`, or `%APPDATA%\dupefindr` on Windows.
+pub fn default_config_path() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let base = std::env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(not(target_os = "windows"))]
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    base.map(|dir| dir.join("dupefindr").join(CONFIG_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_config_load() {
+        let temp_dir = tempdir().unwrap();
+        let config_path = temp_dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(
+            &config_path,
+            "protect = [\"/srv/master\", \"/home/*/Documents\"]\n",
+        )
+        .unwrap();
+        let config = Config::load(&config_path).unwrap();
+        assert_eq!(config.protect, vec!["/srv/master", "/home/*/Documents"]);
+
+        // everything is optional
+        std::fs::write(&config_path, "").unwrap();
+        assert_eq!(Config::load(&config_path).unwrap(), Config::default());
+
+        // misspelt settings are errors, rather than silently ignored
+        std::fs::write(&config_path, "protected = [\"/srv/master\"]\n").unwrap();
+        let error = Config::load(&config_path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
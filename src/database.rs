@@ -0,0 +1,425 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::io;
+use std::path::Path;
+
+use crate::report::{Report, ReportFile, ReportOutcome};
+
+/// The tables of the results database.  Every run appends to them under a new run id.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at TEXT NOT NULL,
+    tool_version TEXT NOT NULL,
+    command TEXT NOT NULL,
+    root_path TEXT NOT NULL,
+    hash_algorithm TEXT NOT NULL,
+    dry_run INTEGER NOT NULL,
+    duplicate_sets INTEGER NOT NULL,
+    duplicate_files INTEGER NOT NULL,
+    wasted_bytes INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    directory TEXT NOT NULL,
+    name TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS hashes (
+    id INTEGER PRIMARY KEY,
+    algorithm TEXT NOT NULL,
+    value TEXT NOT NULL,
+    UNIQUE (algorithm, value)
+);
+CREATE TABLE IF NOT EXISTS duplicate_sets (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    hash_id INTEGER NOT NULL REFERENCES hashes (id),
+    keeper_file_id INTEGER REFERENCES files (id),
+    result TEXT NOT NULL,
+    normalized_match INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS set_files (
+    set_id INTEGER NOT NULL REFERENCES duplicate_sets (id),
+    file_id INTEGER NOT NULL REFERENCES files (id),
+    role TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    modified_at TEXT NOT NULL,
+    PRIMARY KEY (set_id, file_id)
+);
+CREATE TABLE IF NOT EXISTS actions (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    set_id INTEGER NOT NULL REFERENCES duplicate_sets (id),
+    file_id INTEGER NOT NULL REFERENCES files (id),
+    action TEXT NOT NULL,
+    result TEXT NOT NULL,
+    destination TEXT,
+    error_kind TEXT,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS duplicate_sets_run ON duplicate_sets (run_id);
+CREATE INDEX IF NOT EXISTS actions_run ON actions (run_id);
+CREATE VIEW IF NOT EXISTS duplicates AS
+    SELECT runs.id AS run_id, runs.started_at, files.directory, files.path, set_files.size,
+           hashes.algorithm, hashes.value AS hash, duplicate_sets.result
+    FROM set_files
+    JOIN duplicate_sets ON duplicate_sets.id = set_files.set_id
+    JOIN runs ON runs.id = duplicate_sets.run_id
+    JOIN hashes ON hashes.id = duplicate_sets.hash_id
+    JOIN files ON files.id = set_files.file_id
+    WHERE set_files.role = 'duplicate' AND duplicate_sets.result <> 'Mismatched';
+";
+
+/// # Database
+/// A SQLite database of the results of every run, so they can be queried across runs.
+///
+/// * `connection` - The connection to the database.
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Open a results database, creating it and its tables if they do not exist.
+    pub fn open(path: &Path) -> Result<Database, io::Error> {
+        let connection = Connection::open(path).map_err(io::Error::other)?;
+        connection
+            .execute_batch("PRAGMA foreign_keys = ON;")
+            .and_then(|_| connection.execute_batch(SCHEMA))
+            .map_err(io::Error::other)?;
+        Ok(Database { connection })
+    }
+
+    /// Record a run and its duplicate sets.  Everything is written in one transaction, so a failed
+    /// run leaves nothing behind.
+    ///
+    /// * `report` - The results of the run.
+    /// * `Result<i64, io::Error>` - The id of the run.
+    pub fn record_run(&mut self, report: &Report) -> Result<i64, io::Error> {
+        let transaction = self.connection.transaction().map_err(io::Error::other)?;
+        let run_id = insert_run(&transaction, report).map_err(io::Error::other)?;
+        transaction.commit().map_err(io::Error::other)?;
+        Ok(run_id)
+    }
+
+    /// The connection to the database, for running queries.
+    #[cfg(test)]
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+/// # insert_run
+/// Insert a run, its files, hashes, duplicate sets and actions.
+fn insert_run(transaction: &Transaction, report: &Report) -> Result<i64, rusqlite::Error> {
+    // files that did not match byte-for-byte are not duplicates, so they are not counted
+    let duplicate_sets: Vec<_> = report
+        .sets
+        .iter()
+        .filter(|set| set.result != "Mismatched")
+        .collect();
+    let duplicate_files: usize = duplicate_sets.iter().map(|set| set.extras.len()).sum();
+    let wasted_bytes: u64 = duplicate_sets.iter().map(|set| set.wasted_bytes()).sum();
+    transaction.execute(
+        "INSERT INTO runs (started_at, tool_version, command, root_path, hash_algorithm, dry_run,
+                           duplicate_sets, duplicate_files, wasted_bytes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            timestamp(&report.metadata.created_at),
+            report.metadata.version,
+            report.metadata.command,
+            report.options.path,
+            report.options.hash_algorithm.to_string(),
+            report.options.dry_run,
+            duplicate_sets.len() as i64,
+            duplicate_files as i64,
+            wasted_bytes as i64,
+        ],
+    )?;
+    let run_id = transaction.last_insert_rowid();
+
+    for set in &report.sets {
+        let hash_id = upsert_hash(transaction, &set.algorithm.to_string(), &set.hash)?;
+        let keeper_id = set
+            .keeper
+            .as_ref()
+            .map(|keeper| upsert_file(transaction, &keeper.path))
+            .transpose()?;
+        transaction.execute(
+            "INSERT INTO duplicate_sets (run_id, hash_id, keeper_file_id, result, normalized_match)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![run_id, hash_id, keeper_id, set.result, set.normalized_match],
+        )?;
+        let set_id = transaction.last_insert_rowid();
+
+        if let (Some(keeper), Some(keeper_id)) = (&set.keeper, keeper_id) {
+            insert_set_file(transaction, set_id, keeper_id, "keeper", keeper)?;
+        }
+        for extra in &set.extras {
+            let file_id = upsert_file(transaction, &extra.path)?;
+            insert_set_file(transaction, set_id, file_id, "duplicate", extra)?;
+            // each hardlink of a duplicate has its own action, since it is processed separately
+            for path in std::iter::once(&extra.path).chain(&extra.hardlinks) {
+                let file_id = upsert_file(transaction, path)?;
+                let outcome = set.outcome(path);
+                transaction.execute(
+                    "INSERT INTO actions (run_id, set_id, file_id, action, result, destination, error_kind, error)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        run_id,
+                        set_id,
+                        file_id,
+                        report.metadata.command,
+                        action_result(outcome, report.options.dry_run),
+                        outcome.and_then(|outcome| outcome.destination.as_deref()),
+                        outcome.and_then(|outcome| outcome.error_kind.as_deref()),
+                        outcome.and_then(|outcome| outcome.error.as_deref()),
+                    ],
+                )?;
+            }
+        }
+    }
+    Ok(run_id)
+}
+
+/// # action_result
+/// What happened to a file: `ok` if the action succeeded, `failed` if it did not, and `skipped` if
+/// nothing was done to it, because it was not processed or the run was a dry run.
+fn action_result(outcome: Option<&ReportOutcome>, dry_run: bool) -> &'static str {
+    match outcome {
+        Some(outcome) if outcome.error.is_some() => "failed",
+        Some(_) if !dry_run => "ok",
+        _ => "skipped",
+    }
+}
+
+/// # insert_set_file
+/// Record a file as a member of a duplicate set, as it was during the run.
+fn insert_set_file(
+    transaction: &Transaction,
+    set_id: i64,
+    file_id: i64,
+    role: &str,
+    file: &ReportFile,
+) -> Result<(), rusqlite::Error> {
+    transaction.execute(
+        "INSERT OR IGNORE INTO set_files (set_id, file_id, role, size, created_at, modified_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            set_id,
+            file_id,
+            role,
+            file.size as i64,
+            timestamp(&file.created_at),
+            timestamp(&file.modified_at)
+        ],
+    )?;
+    Ok(())
+}
+
+/// # upsert_file
+/// Get the id of a file, adding it if it has not been seen before.
+fn upsert_file(transaction: &Transaction, path: &str) -> Result<i64, rusqlite::Error> {
+    let existing = transaction
+        .query_row("SELECT id FROM files WHERE path = ?1", [path], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    let file_path = Path::new(path);
+    let directory = file_path
+        .parent()
+        .map(|parent| parent.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    transaction.execute(
+        "INSERT INTO files (path, directory, name) VALUES (?1, ?2, ?3)",
+        params![path, directory, name],
+    )?;
+    Ok(transaction.last_insert_rowid())
+}
+
+/// # upsert_hash
+/// Get the id of a hash, adding it if it has not been seen before.
+fn upsert_hash(
+    transaction: &Transaction,
+    algorithm: &str,
+    value: &str,
+) -> Result<i64, rusqlite::Error> {
+    let existing = transaction
+        .query_row(
+            "SELECT id FROM hashes WHERE algorithm = ?1 AND value = ?2",
+            [algorithm, value],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    transaction.execute(
+        "INSERT INTO hashes (algorithm, value) VALUES (?1, ?2)",
+        [algorithm, value],
+    )?;
+    Ok(transaction.last_insert_rowid())
+}
+
+/// # timestamp
+/// Format a time the way SQLite date functions expect.
+fn timestamp(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::HashAlgorithm;
+    use crate::report::tests::report_file;
+    use crate::report::{ReportOutcome, ReportSet};
+    use tempfile::tempdir;
+
+    fn report(extras: &[&str]) -> Report {
+        crate::report::tests::report(
+            "delete",
+            vec![ReportSet {
+                hash: "abc".to_string(),
+                algorithm: HashAlgorithm::Md5,
+                keeper: Some(report_file("/data/keep.txt", 100)),
+                extras: extras.iter().map(|path| report_file(path, 100)).collect(),
+                result: "Deleted".to_string(),
+                normalized_match: false,
+                outcomes: extras
+                    .iter()
+                    .map(|path| ReportOutcome {
+                        path: path.to_string(),
+                        destination: None,
+                        error_kind: Some("NotFound".to_string()),
+                        error: Some("No such file or directory".to_string()),
+                    })
+                    .collect(),
+                reason: None,
+            }],
+        )
+    }
+
+    #[test]
+    fn test_record_run() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("results.sqlite");
+        let mut database = Database::open(&db_path).unwrap();
+        let first = database.record_run(&report(&["/data/a/copy.txt"])).unwrap();
+        drop(database);
+
+        // a second run appends to the same database under a new run id
+        let mut database = Database::open(&db_path).unwrap();
+        let second = database
+            .record_run(&report(&["/data/a/copy.txt", "/data/b/copy.txt"]))
+            .unwrap();
+        assert_ne!(first, second);
+
+        let connection = database.connection();
+        let count = |sql: &str| -> i64 { connection.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM runs"), 2);
+        // files and hashes are shared between runs
+        assert_eq!(count("SELECT COUNT(*) FROM files"), 3);
+        assert_eq!(count("SELECT COUNT(*) FROM hashes"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM duplicate_sets"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM actions"), 3);
+        assert_eq!(
+            count("SELECT COUNT(*) FROM actions WHERE error_kind = 'NotFound'"),
+            3
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM actions WHERE result = 'failed'"),
+            3
+        );
+        assert_eq!(
+            count("SELECT wasted_bytes FROM runs ORDER BY id DESC LIMIT 1"),
+            200
+        );
+
+        let mut statement = connection
+            .prepare(
+                "SELECT directory, COUNT(*) FROM duplicates WHERE run_id = ?1
+                 GROUP BY directory ORDER BY directory",
+            )
+            .unwrap();
+        let directories: Vec<(String, i64)> = statement
+            .query_map([second], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(
+            directories,
+            vec![("/data/a".to_string(), 1), ("/data/b".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_record_run_action_results() {
+        let temp_dir = tempdir().unwrap();
+        let mut database = Database::open(&temp_dir.path().join("results.sqlite")).unwrap();
+        let outcome = |path: &str, error: Option<&str>| ReportOutcome {
+            path: path.to_string(),
+            destination: None,
+            error_kind: error.map(|_| "Other".to_string()),
+            error: error.map(|error| error.to_string()),
+        };
+        let mut linked = report_file("/data/linked.txt", 100);
+        linked.hardlinks = vec!["/data/link.txt".to_string()];
+        let mut report = crate::report::tests::report(
+            "delete",
+            vec![ReportSet {
+                hash: "abc".to_string(),
+                algorithm: HashAlgorithm::Md5,
+                keeper: Some(report_file("/data/keep.txt", 100)),
+                extras: vec![
+                    linked,
+                    report_file("/data/failed.txt", 100),
+                    report_file("/data/skipped.txt", 100),
+                ],
+                result: "Deleted".to_string(),
+                normalized_match: false,
+                outcomes: vec![
+                    outcome("/data/linked.txt", None),
+                    outcome("/data/link.txt", None),
+                    outcome("/data/failed.txt", Some("Permission denied")),
+                ],
+                reason: None,
+            }],
+        );
+        report.options.dry_run = false;
+        database.record_run(&report).unwrap();
+
+        let mut statement = database
+            .connection()
+            .prepare(
+                "SELECT files.path, actions.result FROM actions
+                 JOIN files ON files.id = actions.file_id ORDER BY files.path",
+            )
+            .unwrap();
+        let results: Vec<(String, String)> = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        let expected = [
+            ("/data/failed.txt", "failed"),
+            ("/data/link.txt", "ok"),
+            ("/data/linked.txt", "ok"),
+            ("/data/skipped.txt", "skipped"),
+        ];
+        assert_eq!(
+            results,
+            expected
+                .iter()
+                .map(|(path, result)| (path.to_string(), result.to_string()))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use clap::ValueEnum;
+use md5::Digest;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// # HashAlgorithm
+/// The algorithm used to hash the contents of files.
+///
+/// * `Md5` - MD5, the original dupefindr algorithm.
+/// * `Xxh3` - xxHash3 (128 bit), a fast non-cryptographic hash.
+/// * `Blake3` - BLAKE3, a fast cryptographic hash.
+/// * `Sha256` - SHA-256, for compliance reports.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Md5,
+    Xxh3,
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Create a new hasher for this algorithm.
+    pub fn new_hasher(&self) -> Box<dyn Hasher> {
+        match self {
+            HashAlgorithm::Md5 => Box::new(Md5Hasher(md5::Md5::new())),
+            HashAlgorithm::Xxh3 => Box::new(Xxh3Hasher(xxhash_rust::xxh3::Xxh3::new())),
+            HashAlgorithm::Blake3 => Box::new(Blake3Hasher(blake3::Hasher::new())),
+            HashAlgorithm::Sha256 => Box::new(Sha256Hasher(sha2::Sha256::new())),
+        }
+    }
+
+    /// The name of the algorithm, as written into reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Xxh3 => "xxh3",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// # Hasher
+/// Trait implemented by each hash algorithm.
+///
+/// * `update` - Add data to the hash.
+/// * `finalize` - Consume the hasher and return the hash as a lowercase hex string.
+pub trait Hasher: Send {
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self: Box<Self>) -> String;
+}
+
+struct Md5Hasher(md5::Md5);
+
+impl Hasher for Md5Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> String {
+        format!("{:x}", self.0.finalize())
+    }
+}
+
+struct Xxh3Hasher(xxhash_rust::xxh3::Xxh3);
+
+impl Hasher for Xxh3Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> String {
+        format!("{:032x}", self.0.digest128())
+    }
+}
+
+struct Blake3Hasher(blake3::Hasher);
+
+impl Hasher for Blake3Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> String {
+        self.0.finalize().to_hex().to_string()
+    }
+}
+
+struct Sha256Hasher(sha2::Sha256);
+
+impl Hasher for Sha256Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> String {
+        format!("{:x}", self.0.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_of(algorithm: HashAlgorithm, data: &[u8]) -> String {
+        let mut hasher = algorithm.new_hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    #[test]
+    fn test_hash_algorithms() {
+        assert_eq!(
+            hash_of(HashAlgorithm::Md5, b"abc"),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            hash_of(HashAlgorithm::Sha256, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash_of(HashAlgorithm::Blake3, b"abc"),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(hash_of(HashAlgorithm::Xxh3, b"abc").len(), 32);
+        assert_ne!(
+            hash_of(HashAlgorithm::Xxh3, b"abc"),
+            hash_of(HashAlgorithm::Xxh3, b"abd")
+        );
+    }
+
+    #[test]
+    fn test_hash_in_chunks() {
+        // hashing in several updates matches hashing in one
+        for algorithm in HashAlgorithm::value_variants() {
+            let mut hasher = algorithm.new_hasher();
+            hasher.update(b"a");
+            hasher.update(b"bc");
+            assert_eq!(hasher.finalize(), hash_of(*algorithm, b"abc"));
+        }
+    }
+
+    #[test]
+    fn test_hash_algorithm_name() {
+        assert_eq!(HashAlgorithm::Md5.to_string(), "md5");
+        assert_eq!(HashAlgorithm::Xxh3.to_string(), "xxh3");
+        assert_eq!(HashAlgorithm::Blake3.to_string(), "blake3");
+        assert_eq!(HashAlgorithm::Sha256.to_string(), "sha256");
+    }
+}
@@ -0,0 +1,430 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::quarantine::{self, Manifest};
+
+/// # JournalOperation
+/// The operations recorded in a journal.
+///
+/// * `Move` - The file was moved to the destination.
+/// * `Copy` - The file was copied to the destination.
+/// * `Delete` - The file was deleted permanently.
+/// * `Trash` - The file was moved to the trash, at the destination.
+/// * `Link` - The file was replaced with a hardlink to the destination.
+/// * `Symlink` - The file was replaced with a symbolic link to the destination.
+/// * `Reflink` - The file shares its storage with the destination.
+/// * `Quarantine` - The file was moved to quarantine, at the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalOperation {
+    Move,
+    Copy,
+    Delete,
+    Trash,
+    Link,
+    Symlink,
+    Reflink,
+    Quarantine,
+}
+
+/// # JournalEntry
+/// One operation on one file, written as a line of JSON.
+///
+/// * `operation` - What was done to the file.
+/// * `source` - The path of the file.
+/// * `destination` - Where the file went, or the file it was linked to.
+/// * `hash` - The hash of the file.
+/// * `timestamp` - When the operation happened.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub operation: JournalOperation,
+    pub source: String,
+    pub destination: Option<String>,
+    pub hash: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// # UndoSummary
+/// What happened when a journal was undone.
+///
+/// * `undone` - The number of operations reversed.
+/// * `errors` - A message for each operation that could not be reversed.
+#[derive(Debug, Default)]
+pub struct UndoSummary {
+    pub undone: usize,
+    pub errors: Vec<String>,
+}
+
+/// # Journal
+/// An append only record of the operations of a run.  The file is created when the first
+/// operation is recorded, so runs that change nothing leave no journal behind.
+///
+/// * `path` - The path of the journal file.
+/// * `file` - The journal file, once it has been created.
+pub struct Journal {
+    path: PathBuf,
+    file: RefCell<Option<fs::File>>,
+}
+
+impl Journal {
+    /// Create a journal that will be written to a file.
+    pub fn new(path: &Path) -> Journal {
+        Journal {
+            path: path.to_path_buf(),
+            file: RefCell::new(None),
+        }
+    }
+
+    /// The path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// True once an operation has been recorded.
+    pub fn is_written(&self) -> bool {
+        self.file.borrow().is_some()
+    }
+
+    /// Append an operation to the journal.  Each line is flushed to disk, so the journal survives a crash.
+    pub fn record(&self, entry: &JournalEntry) -> Result<(), io::Error> {
+        let mut file = self.file.borrow_mut();
+        if file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            *file = Some(
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+        let file = file.as_mut().unwrap();
+        let mut line = serde_json::to_string(entry).map_err(io::Error::other)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+
+    /// Read every entry of a journal file.
+    pub fn load(path: &Path) -> Result<Vec<JournalEntry>, io::Error> {
+        let reader = io::BufReader::new(fs::File::open(path)?);
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line).map_err(io::Error::other)?);
+        }
+        Ok(entries)
+    }
+}
+
+/// # default_journal_path
+/// The location of the journal for a run started now.
+/// `$XDG_STATE_HOME/dupefindr/journals`, falling back to `~/.local/state/dupefindr/journals`, or
+/// `%LOCALAPPDATA%\dupefindr\journals` on Windows.
+pub fn default_journal_path() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let base = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+    #[cfg(not(target_os = "windows"))]
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        });
+    let file_name = format!("{}.jsonl", chrono::Local::now().format("%Y%m%d-%H%M%S%.3f"));
+    base.map(|dir| dir.join("dupefindr").join("journals").join(file_name))
+}
+
+/// # undo
+/// Reverse the operations of a journal, newest first.
+///
+/// * `entries` - The entries of the journal.
+/// * `dry_run` - Report what would be undone without changing anything.
+pub fn undo(entries: &[JournalEntry], dry_run: bool) -> UndoSummary {
+    let mut summary = UndoSummary::default();
+    for entry in entries.iter().rev() {
+        let result = match dry_run {
+            true => check_undo(entry),
+            false => check_undo(entry).and_then(|_| undo_entry(entry)),
+        };
+        match result {
+            Ok(()) => summary.undone += 1,
+            Err(e) => summary.errors.push(format!(
+                "Unable to undo {:?} of {}: {}",
+                entry.operation, entry.source, e
+            )),
+        }
+    }
+    summary
+}
+
+/// # check_undo
+/// Check that an operation can be reversed, without changing anything.
+fn check_undo(entry: &JournalEntry) -> Result<(), io::Error> {
+    let destination = entry.destination.as_deref().unwrap_or_default();
+    match entry.operation {
+        JournalOperation::Delete => Err(io::Error::other("the file was deleted permanently")),
+        JournalOperation::Reflink => Err(io::Error::other(
+            "reflinked files are already independent copies",
+        )),
+        JournalOperation::Move | JournalOperation::Trash | JournalOperation::Quarantine => {
+            if Path::new(&entry.source).exists() {
+                Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "a file already exists at the original path",
+                ))
+            } else if !Path::new(destination).exists() {
+                Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} no longer exists", destination),
+                ))
+            } else {
+                Ok(())
+            }
+        }
+        JournalOperation::Copy => Ok(()),
+        JournalOperation::Link | JournalOperation::Symlink => {
+            if !Path::new(destination).exists() {
+                Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("the kept file {} no longer exists", destination),
+                ))
+            } else if !is_link_to(
+                entry.operation,
+                Path::new(&entry.source),
+                Path::new(destination),
+            )? {
+                Err(io::Error::other(format!(
+                    "{} is no longer a link to {}",
+                    entry.source, destination
+                )))
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+/// # is_link_to
+/// True if a file is still the link to the kept file that was made, rather than a file that has
+/// replaced it since.  A symbolic link must point to the kept file, and a hardlink must be the same
+/// file as the kept file.
+fn is_link_to(
+    operation: JournalOperation,
+    source: &Path,
+    destination: &Path,
+) -> Result<bool, io::Error> {
+    let metadata = fs::symlink_metadata(source)?;
+    if operation == JournalOperation::Symlink {
+        return Ok(
+            metadata.is_symlink() && fs::canonicalize(source)? == fs::canonicalize(destination)?
+        );
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let destination_metadata = fs::metadata(destination)?;
+        Ok(!metadata.is_symlink()
+            && metadata.dev() == destination_metadata.dev()
+            && metadata.ino() == destination_metadata.ino())
+    }
+    #[cfg(not(unix))]
+    Err(io::Error::other(
+        "hardlinks can not be checked on this platform",
+    ))
+}
+
+/// # undo_entry
+/// Reverse an operation.
+fn undo_entry(entry: &JournalEntry) -> Result<(), io::Error> {
+    let source = Path::new(&entry.source);
+    let destination = Path::new(entry.destination.as_deref().unwrap_or_default());
+    match entry.operation {
+        JournalOperation::Move => quarantine::move_file(destination, source),
+        JournalOperation::Copy => match fs::remove_file(destination) {
+            // the copy is already gone
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        },
+        JournalOperation::Trash => {
+            quarantine::move_file(destination, source)?;
+            // the trash keeps the details of each file in info/<name>.trashinfo, next to files/<name>
+            if let (Some(files_dir), Some(file_name)) =
+                (destination.parent(), destination.file_name())
+            {
+                if let Some(trash) = files_dir.parent() {
+                    let mut info_name = file_name.to_os_string();
+                    info_name.push(".trashinfo");
+                    let _ = fs::remove_file(trash.join("info").join(info_name));
+                }
+            }
+            Ok(())
+        }
+        JournalOperation::Quarantine => {
+            quarantine::move_file(destination, source)?;
+            // the quarantine folder holds <hash>/<file>, next to its manifest
+            if let Some(location) = destination.parent().and_then(|parent| parent.parent()) {
+                let mut manifest = Manifest::load(location)?;
+                if manifest.remove(&destination.to_string_lossy()) {
+                    manifest.save()?;
+                }
+            }
+            Ok(())
+        }
+        JournalOperation::Link | JournalOperation::Symlink => {
+            // replace the link with a copy of the file it points to, so the file is independent again
+            let file_name = source
+                .file_name()
+                .ok_or_else(|| io::Error::other("invalid file name"))?;
+            let temp_path = quarantine::reserve_unused_path(
+                &source.with_file_name(format!(".{}.dupefindr-undo", file_name.to_string_lossy())),
+            )?;
+            if let Err(e) =
+                fs::copy(destination, &temp_path).and_then(|_| fs::rename(&temp_path, source))
+            {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e);
+            }
+            Ok(())
+        }
+        JournalOperation::Delete | JournalOperation::Reflink => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(operation: JournalOperation, source: &Path, destination: &Path) -> JournalEntry {
+        JournalEntry {
+            operation,
+            source: source.to_str().unwrap().to_string(),
+            destination: Some(destination.to_str().unwrap().to_string()),
+            hash: "hash".to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_journal_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("journals").join("run.jsonl");
+        let journal = Journal::new(&path);
+        assert!(!journal.is_written());
+        assert!(!path.exists());
+
+        let first = entry(
+            JournalOperation::Move,
+            Path::new("/a/file.txt"),
+            Path::new("/b/file.txt"),
+        );
+        let second = JournalEntry {
+            operation: JournalOperation::Delete,
+            destination: None,
+            ..first.clone()
+        };
+        journal.record(&first).unwrap();
+        journal.record(&second).unwrap();
+        assert!(journal.is_written());
+
+        let contents = fs::read_to_string(journal.path()).unwrap();
+        assert!(contents
+            .lines()
+            .next()
+            .unwrap()
+            .contains("\"operation\":\"move\""));
+        assert_eq!(Journal::load(&path).unwrap(), vec![first, second]);
+    }
+
+    #[test]
+    fn test_undo() {
+        let temp_dir = tempdir().unwrap();
+        let keeper = temp_dir.path().join("keeper.txt");
+        let moved_from = temp_dir.path().join("moved.txt");
+        let moved_to = temp_dir.path().join("location").join("moved.txt");
+        let copied_to = temp_dir.path().join("copy.txt");
+        let linked = temp_dir.path().join("linked.txt");
+        fs::create_dir(moved_to.parent().unwrap()).unwrap();
+        for path in [&keeper, &moved_to, &copied_to] {
+            fs::write(path, "contents").unwrap();
+        }
+        fs::hard_link(&keeper, &linked).unwrap();
+        // a file using the temporary name of the copy is left alone
+        let other = temp_dir.path().join(".linked.txt.dupefindr-undo");
+        fs::write(&other, "other").unwrap();
+
+        let entries = vec![
+            entry(JournalOperation::Move, &moved_from, &moved_to),
+            entry(JournalOperation::Copy, &keeper, &copied_to),
+            entry(JournalOperation::Link, &linked, &keeper),
+            entry(
+                JournalOperation::Delete,
+                &temp_dir.path().join("gone.txt"),
+                &keeper,
+            ),
+        ];
+
+        // a dry run changes nothing
+        let summary = undo(&entries, true);
+        assert_eq!(summary.undone, 3);
+        assert_eq!(summary.errors.len(), 1);
+        assert!(moved_to.exists());
+
+        let summary = undo(&entries, false);
+        assert_eq!(summary.undone, 3);
+        assert_eq!(summary.errors.len(), 1);
+        assert!(summary.errors[0].contains("deleted permanently"));
+        assert_eq!(fs::read_to_string(&moved_from).unwrap(), "contents");
+        assert!(!moved_to.exists());
+        assert!(!copied_to.exists());
+        assert_eq!(fs::read_to_string(&linked).unwrap(), "contents");
+        assert_eq!(fs::read_to_string(&other).unwrap(), "other");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(fs::metadata(&keeper).unwrap().nlink(), 1);
+        }
+
+        // undoing again finds the files already back in place
+        let summary = undo(&entries[..1], false);
+        assert_eq!(summary.undone, 0);
+        assert_eq!(summary.errors.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_undo_replaced_link() {
+        let temp_dir = tempdir().unwrap();
+        let keeper = temp_dir.path().join("keeper.txt");
+        let other = temp_dir.path().join("other.txt");
+        let linked = temp_dir.path().join("linked.txt");
+        let symlinked = temp_dir.path().join("symlinked.txt");
+        fs::write(&keeper, "contents").unwrap();
+        fs::write(&other, "other").unwrap();
+        // the links were replaced after they were made
+        fs::write(&linked, "new contents").unwrap();
+        std::os::unix::fs::symlink(&other, &symlinked).unwrap();
+
+        let entries = vec![
+            entry(JournalOperation::Link, &linked, &keeper),
+            entry(JournalOperation::Symlink, &symlinked, &keeper),
+            entry(JournalOperation::Symlink, &keeper, &keeper),
+        ];
+        let summary = undo(&entries, false);
+        assert_eq!(summary.undone, 0);
+        assert_eq!(summary.errors.len(), 3);
+        assert!(summary.errors[0].contains("is no longer a link to"));
+        assert_eq!(fs::read_to_string(&linked).unwrap(), "new contents");
+        assert!(fs::symlink_metadata(&symlinked).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&keeper).unwrap(), "contents");
+    }
+}
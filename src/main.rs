@@ -43,23 +43,38 @@
 /// * `get_command_line_arguments` - Parse and return command line arguments.
 /// * `start_search` - Start the search for duplicate files.
 /// * `get_files_in_directory` - Get files in the specified directory.
+/// * `collapse_hardlinks` - Collapse hardlinks to the same file into one logical file.
+/// * `group_files_by_size` - Group files by size, dropping files with a unique size.
 /// * `identify_duplicates` - Identify duplicate files based on their hash.
 /// * `process_duplicates` - Process the identified duplicate files.
+/// * `verify_duplicates` - Compare duplicates byte-for-byte with the file being kept.
 /// * `process_a_duplicate_file` - Process a single duplicate file based on the command.
-/// * `get_hash_of_file` - Get the MD5 hash of a file.
-/// * `get_md5_hash` - Get the MD5 hash of a buffer.
+/// * `create_plan` - Record the duplicates found and the action to take on them as a plan.
+/// * `apply_plan` - Carry out a plan, skipping files that changed since it was made.
+/// * `get_hash_of_file` - Get the hash of a file.
+/// * `get_normalized_hash_of_file` - Get the hash of a file with CRLF line endings normalized if it is text.
+/// * `get_partial_hash_of_file` - Get the hash of the head and tail of a file.
 /// * `select_duplicate_files` - Select the file to keep and the duplicates to process based on the selection method.
 ///
+use cache::{CacheEntry, CacheKey, HashCache};
 use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand, ValueEnum};
+use config::Config;
+use database::Database;
 use dialoguer_ext::console::{style, Key};
 use dialoguer_ext::theme::ColorfulTheme;
 use dialoguer_ext::Select;
 use errors::{InteractiveError, InteractiveErrorKind};
+use hasher::HashAlgorithm;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use md5::{self, Digest};
-use std::collections::HashMap;
-use std::io::{self, Read};
+use journal::{Journal, JournalEntry, JournalOperation};
+use plan::{ApplySummary, Plan, PlanAction, PlannedFile, PlannedSet};
+use quarantine::{Manifest, QuarantineEntry};
+use report::{
+    Report, ReportFile, ReportFormat, ReportOptions, ReportOutcome, ReportSet, RunMetadata,
+};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Seek, SeekFrom};
 #[cfg(target_os = "windows")]
 use std::os::windows::fs::MetadataExt;
 use std::path::{Path, PathBuf};
@@ -69,12 +84,28 @@ use std::time::UNIX_EPOCH;
 use std::time::{Duration, Instant};
 use std::{fs, thread};
 use threadpool::ThreadPool;
+use trash::TrashFileOperations;
 
+mod cache;
+mod config;
+mod database;
 mod errors;
+mod hasher;
+mod journal;
+mod plan;
+mod quarantine;
+mod reflink;
+mod report;
+mod trash;
 
 const BUFFER_READ_SIZE: usize = 1024 * 1024;
+const TEXT_DETECTION_SIZE: u64 = 8000;
+/// The version of the columns of the csv report, written on every row so scripts can detect the schema
+const CSV_REPORT_SCHEMA_VERSION: u32 = 4;
+/// The largest head or tail in KiB that can be partially hashed (1 TiB), so the size in bytes can not overflow
+const MAX_PARTIAL_HASH_KIB: u64 = 1024 * 1024 * 1024;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "Dupefindr", version)]
 #[command(about = "A tool to find duplicate files", long_about = None)]
 #[command(propagate_version = true)]
@@ -149,28 +180,401 @@ struct SharedOptions {
     /// Defaults to the folder where dupefindr was run
     #[arg(long, default_value = "./dupefindr-report.csv")]
     report_path: String,
+
+    /// Format of the report
+    /// csv has a row for each duplicate, json is a single document with every duplicate set, ndjson has a set on each line,
+    /// html is a single page that can be shared
+    #[arg(long, value_enum, default_value = "csv")]
+    report_format: ReportFormat,
+
+    /// Size in KiB of the start of each file to hash before hashing the full contents
+    /// Files whose partial hashes differ are never fully hashed
+    /// Set both this and partial_hash_tail_kib to 0 to disable partial hashing
+    #[arg(long, default_value = "64", value_parser = clap::value_parser!(u64).range(..=MAX_PARTIAL_HASH_KIB))]
+    partial_hash_head_kib: u64,
+
+    /// Size in KiB of the end of each file to hash before hashing the full contents
+    #[arg(long, default_value = "0", value_parser = clap::value_parser!(u64).range(..=MAX_PARTIAL_HASH_KIB))]
+    partial_hash_tail_kib: u64,
+
+    /// Compare each duplicate byte-for-byte with the file being kept before acting on it
+    /// This is always done for delete and move unless --no-verify is set
+    #[arg(long, default_value = "false")]
+    verify: bool,
+
+    /// Do not compare duplicates byte-for-byte before deleting or moving them
+    #[arg(long, default_value = "false", conflicts_with = "verify")]
+    no_verify: bool,
+
+    /// Treat CRLF and LF line endings as equal when comparing text files
+    /// Sets found this way are reported as a normalized match, as the files are not byte-identical
+    #[arg(long, default_value = "false")]
+    normalize_line_endings: bool,
+
+    /// The algorithm used to hash file contents
+    /// md5 is the default, xxh3 is fastest, blake3 is a fast cryptographic hash, sha256 is for compliance
+    #[arg(long = "hash", value_enum, default_value = "md5")]
+    hash_algorithm: HashAlgorithm,
+
+    /// Do not read or update the hash cache
+    #[arg(long, default_value = "false")]
+    no_cache: bool,
+
+    /// Path of the hash cache
+    /// Defaults to ~/.cache/dupefindr/hashes.csv
+    #[arg(long)]
+    cache_path: Option<String>,
+
+    /// List files that are hardlinks to each other as already linked
+    /// Hardlinks are always treated as one file, since removing one of them frees no space
+    #[arg(long, default_value = "false")]
+    list_hardlinks: bool,
+
+    /// Path of the journal of the changes made, which the undo command can reverse
+    /// Defaults to a new file in ~/.local/state/dupefindr/journals for each run
+    #[arg(long)]
+    journal: Option<String>,
+
+    /// Do not write a journal of the changes made
+    #[arg(long, default_value = "false", conflicts_with = "journal")]
+    no_journal: bool,
+
+    /// Write the duplicates found and what would be done with them to a plan file, instead of acting on them
+    /// The apply command carries out the plan
+    #[arg(long)]
+    plan_out: Option<String>,
+
+    /// Path of a SQLite database to record the results in
+    /// Each run is added under a new run id, so results can be queried across runs
+    #[arg(long)]
+    db: Option<String>,
+
+    /// Keep files under paths matching this wildcard over the other copies, before the method is applied
+    /// Can be given more than once, earlier wildcards are preferred.  Example: /srv/master
+    #[arg(long, value_parser = parse_path_wildcard)]
+    prefer: Vec<glob::Pattern>,
+
+    /// Only keep files under paths matching this wildcard when no other copy can be kept
+    /// Sets where every copy matches are skipped.  Can be given more than once.  Example: /home/*/Downloads
+    #[arg(long, value_parser = parse_path_wildcard)]
+    avoid: Vec<glob::Pattern>,
+
+    /// Never modify files under this path or wildcard, whichever file the method selects
+    /// Can be given more than once, and added to in the config file.  Example: /srv/master
+    #[arg(long, value_parser = parse_path_wildcard)]
+    protect: Vec<glob::Pattern>,
+
+    /// Path of the config file
+    /// Defaults to ~/.config/dupefindr/config.toml, which is only read if it exists
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Do not read the config file
+    #[arg(long, default_value = "false", conflicts_with = "config")]
+    no_config: bool,
+}
+
+/// # parse_path_wildcard
+/// Parse a wildcard given for --prefer, --avoid, --protect or restore --filter.
+/// * `wildcard` - The wildcard.
+fn parse_path_wildcard(wildcard: &str) -> Result<glob::Pattern, glob::PatternError> {
+    glob::Pattern::new(wildcard)
+}
+
+/// # KeeperRules
+/// Path rules that override the file the ranking selects to keep.
+/// --prefer and --avoid are not rules, they lead the ranking (see `KeeperRanking::with_path_rules`).
+///
+/// * `protect` - Files under a matching path are never modified, so they are always kept.
+#[derive(Debug, Clone, Default)]
+struct KeeperRules {
+    protect: Vec<glob::Pattern>,
+}
+
+impl KeeperRules {
+    /// The rules given on the command line.
+    fn from_args(args: &Args) -> KeeperRules {
+        KeeperRules {
+            protect: args.shared.protect.clone(),
+        }
+    }
+
+    /// True if the file is under a path matching a --protect wildcard.
+    fn protects(&self, path: &str) -> bool {
+        self.protect
+            .iter()
+            .any(|pattern| path_matches(pattern, path))
+    }
+
+    /// The first path of a logical file, or of its hardlinks, that is under a path matching a
+    /// --protect wildcard.  Modifying any of them would modify the protected file.
+    fn protected_path<'a>(&self, file: &'a FileInfo) -> Option<&'a str> {
+        std::iter::once(&file.path)
+            .chain(&file.hardlinks)
+            .find(|path| self.protects(path))
+            .map(|path| path.as_str())
+    }
+}
+
+/// # path_matches
+/// True if a wildcard matches a file or one of the folders it is in, as found or as an absolute path.
+/// * `pattern` - The wildcard.
+/// * `path` - The path of the file.
+fn path_matches(pattern: &glob::Pattern, path: &str) -> bool {
+    let path = Path::new(path);
+    let absolute = std::path::absolute(path).ok();
+    std::iter::once(path)
+        .chain(absolute.as_deref())
+        .flat_map(|path| path.ancestors())
+        .any(|ancestor| pattern.matches_path(ancestor))
+}
+
+/// # load_config
+/// Add the settings of the config file to the command line arguments.  The default config file is
+/// only read if it exists, but one given with --config must.
+/// * `args` - The command line arguments.
+/// # Errors
+/// * `io::Error` - The config file could not be read, or has a setting that is not valid.
+fn load_config(args: &mut Args) -> Result<(), io::Error> {
+    if args.shared.no_config {
+        return Ok(());
+    }
+    let path = match &args.shared.config {
+        Some(path) => PathBuf::from(path),
+        None => match config::default_config_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        },
+    };
+    let config = Config::load(&path)?;
+    for wildcard in &config.protect {
+        let pattern = parse_path_wildcard(wildcard).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: protect {}: {}", path.display(), wildcard, e),
+            )
+        })?;
+        args.shared.protect.push(pattern);
+    }
+    Ok(())
 }
 
 /// # Duplicate Selection Method
+/// A criterion of the ranking that selects the file to keep.
 ///
 /// * `Newest` - Select the newest file to keep.
 /// * `Oldest` - Select the oldest file to keep.
+/// * `Largest` - Select the largest file to keep.  Sizes only differ when line endings are normalized.
+/// * `Smallest` - Select the smallest file to keep.
+/// * `ShortestPath` - Select the file with the shortest path to keep.
+/// * `LongestPath` - Select the file with the longest path to keep.
+/// * `Shallowest` - Select the file in the fewest folders to keep.
+/// * `Alphabetical` - Select the file whose path is first alphabetically to keep.
+/// * `MostHardlinks` - Select the file with the most hardlinks to keep.
 /// * `Interactive` - Prompt user to select file to keep
 #[derive(ValueEnum, Debug, Clone, PartialEq)]
 enum DuplicateSelectionMethod {
     Newest,
     Oldest,
+    Largest,
+    Smallest,
+    ShortestPath,
+    LongestPath,
+    Shallowest,
+    Alphabetical,
+    MostHardlinks,
     Interactive,
 }
 
+impl DuplicateSelectionMethod {
+    /// # compare
+    /// Order two files by how the method prefers to keep them, the file to keep first.
+    /// * `a` - The first file.
+    /// * `b` - The second file.
+    /// * `Ordering` - Less if `a` is kept over `b`.
+    fn compare(&self, a: &FileInfo, b: &FileInfo) -> std::cmp::Ordering {
+        match self {
+            DuplicateSelectionMethod::Newest => b.modified_at.cmp(&a.modified_at),
+            DuplicateSelectionMethod::Oldest => a.modified_at.cmp(&b.modified_at),
+            DuplicateSelectionMethod::Largest => b.size.cmp(&a.size),
+            DuplicateSelectionMethod::Smallest => a.size.cmp(&b.size),
+            DuplicateSelectionMethod::ShortestPath => {
+                a.path.chars().count().cmp(&b.path.chars().count())
+            }
+            DuplicateSelectionMethod::LongestPath => {
+                b.path.chars().count().cmp(&a.path.chars().count())
+            }
+            DuplicateSelectionMethod::Shallowest => path_depth(&a.path).cmp(&path_depth(&b.path)),
+            DuplicateSelectionMethod::MostHardlinks => b.links.cmp(&a.links),
+            DuplicateSelectionMethod::Alphabetical => a.path.cmp(&b.path),
+            DuplicateSelectionMethod::Interactive => std::cmp::Ordering::Equal,
+        }
+    }
+}
+
+/// # RankingCriterion
+/// One criterion of a keeper ranking.
+///
+/// * `Method` - Rank files by a selection method.
+/// * `Prefer` - Rank files under paths matching the wildcard first.
+/// * `Avoid` - Rank files under paths matching the wildcard last.
+#[derive(Debug, Clone, PartialEq)]
+enum RankingCriterion {
+    Method(DuplicateSelectionMethod),
+    Prefer(glob::Pattern),
+    Avoid(glob::Pattern),
+}
+
+impl RankingCriterion {
+    /// # compare
+    /// Order two files by the criterion, the file to keep first.
+    /// * `a` - The first file.
+    /// * `b` - The second file.
+    /// * `Ordering` - Less if `a` is kept over `b`.
+    fn compare(&self, a: &FileInfo, b: &FileInfo) -> std::cmp::Ordering {
+        match self {
+            RankingCriterion::Method(method) => method.compare(a, b),
+            // false orders before true, so files that match are compared as false to come first
+            RankingCriterion::Prefer(pattern) => {
+                (!path_matches(pattern, &a.path)).cmp(&!path_matches(pattern, &b.path))
+            }
+            RankingCriterion::Avoid(pattern) => {
+                path_matches(pattern, &a.path).cmp(&path_matches(pattern, &b.path))
+            }
+        }
+    }
+}
+
+/// # KeeperRanking
+/// The criteria that select the file to keep, given to --method as a comma separated list such as
+/// `prefer:/archive,oldest,shortest-path`.  Files are compared by each criterion in turn, each one only
+/// breaking the ties of those before it.  Files that tie on every criterion are kept by path, alphabetically,
+/// so the same file is kept every run.
+///
+/// * `criteria` - The criteria, most important first.
+#[derive(Debug, Clone, PartialEq)]
+struct KeeperRanking {
+    criteria: Vec<RankingCriterion>,
+}
+
+impl KeeperRanking {
+    /// True if the user is prompted to select the file to keep.
+    fn is_interactive(&self) -> bool {
+        self.criteria.contains(&RankingCriterion::Method(
+            DuplicateSelectionMethod::Interactive,
+        ))
+    }
+
+    /// # with_path_rules
+    /// The ranking with the --prefer and --avoid wildcards ahead of its own criteria.  Avoided files
+    /// come last whether or not they are also preferred, then preferred files come first in the order
+    /// the wildcards were given.
+    /// * `prefer` - The --prefer wildcards.
+    /// * `avoid` - The --avoid wildcards.
+    fn with_path_rules(&self, prefer: &[glob::Pattern], avoid: &[glob::Pattern]) -> KeeperRanking {
+        let criteria = avoid
+            .iter()
+            .cloned()
+            .map(RankingCriterion::Avoid)
+            .chain(prefer.iter().cloned().map(RankingCriterion::Prefer))
+            .chain(self.criteria.iter().cloned())
+            .collect();
+        KeeperRanking { criteria }
+    }
+
+    /// True if the file is under a path matching an avoid criterion.
+    fn avoids(&self, path: &str) -> bool {
+        self.criteria.iter().any(|criterion| match criterion {
+            RankingCriterion::Avoid(pattern) => path_matches(pattern, path),
+            _ => false,
+        })
+    }
+
+    /// # compare
+    /// Order two files by the criteria, the file to keep first.
+    /// * `a` - The first file.
+    /// * `b` - The second file.
+    /// * `Ordering` - Less if `a` is kept over `b`.
+    fn compare(&self, a: &FileInfo, b: &FileInfo) -> std::cmp::Ordering {
+        self.criteria
+            .iter()
+            .map(|criterion| criterion.compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.path.cmp(&b.path))
+    }
+}
+
+impl From<DuplicateSelectionMethod> for KeeperRanking {
+    fn from(method: DuplicateSelectionMethod) -> KeeperRanking {
+        KeeperRanking {
+            criteria: vec![RankingCriterion::Method(method)],
+        }
+    }
+}
+
+impl std::str::FromStr for KeeperRanking {
+    type Err = String;
+
+    /// Parse a comma separated list of methods, `prefer:<WILDCARD>` and `avoid:<WILDCARD>`.
+    fn from_str(value: &str) -> Result<KeeperRanking, String> {
+        let mut criteria = Vec::new();
+        for criterion in value.split(',').map(str::trim) {
+            let wildcard = |wildcard: &str| {
+                if wildcard.is_empty() {
+                    return Err(format!("'{}' is missing a wildcard", criterion));
+                }
+                parse_path_wildcard(wildcard)
+                    .map_err(|e| format!("'{}' has an invalid wildcard: {}", criterion, e))
+            };
+            let parsed = if let Some(prefer) = criterion.strip_prefix("prefer:") {
+                RankingCriterion::Prefer(wildcard(prefer)?)
+            } else if let Some(avoid) = criterion.strip_prefix("avoid:") {
+                RankingCriterion::Avoid(wildcard(avoid)?)
+            } else {
+                let method = <DuplicateSelectionMethod as ValueEnum>::from_str(criterion, true)
+                    .map_err(|_| {
+                        let methods: Vec<String> = DuplicateSelectionMethod::value_variants()
+                            .iter()
+                            .filter_map(|method| method.to_possible_value())
+                            .map(|method| method.get_name().to_string())
+                            .collect();
+                        format!(
+                            "unknown criterion '{}', expected one of {}, prefer:<WILDCARD> or avoid:<WILDCARD>",
+                            criterion,
+                            methods.join(", ")
+                        )
+                    })?;
+                RankingCriterion::Method(method)
+            };
+            criteria.push(parsed);
+        }
+        let interactive = RankingCriterion::Method(DuplicateSelectionMethod::Interactive);
+        if criteria.len() > 1 && criteria.contains(&interactive) {
+            return Err("interactive can not be combined with other criteria".to_string());
+        }
+        Ok(KeeperRanking { criteria })
+    }
+}
+
+/// # path_depth
+/// The number of folders and the file name in a path.
+/// * `path` - The path of the file.
+fn path_depth(path: &str) -> usize {
+    Path::new(path)
+        .components()
+        .filter(|component| matches!(component, std::path::Component::Normal(_)))
+        .count()
+}
+
 #[derive(Subcommand, Debug, PartialEq, Clone)]
 enum Commands {
     #[command(name = "find", about = "Find duplicate files")]
     Find {
-        /// Method to select the file to keep
-        /// Example: newest, oldest, largest, smallest
+        /// Method to select the file to keep, or a comma separated list of them, most important first
+        /// Example: newest, or prefer:/archive,oldest,shortest-path
         #[arg(short, long, default_value = "newest")]
-        method: DuplicateSelectionMethod,
+        method: KeeperRanking,
     },
 
     #[command(name = "move", about = "Move duplicate files to a new location")]
@@ -179,10 +583,10 @@ enum Commands {
         #[arg(short, long)]
         location: String,
 
-        /// Method to select the file to keep
-        /// Example: newest, oldest, largest, smallest
+        /// Method to select the file to keep, or a comma separated list of them, most important first
+        /// Example: newest, or prefer:/archive,oldest,shortest-path
         #[arg(short, long, default_value = "newest")]
-        method: DuplicateSelectionMethod,
+        method: KeeperRanking,
 
         // do not create subdirectories in the destination
         #[arg(short, long, default_value = "false")]
@@ -202,9 +606,10 @@ enum Commands {
         #[arg(short, long)]
         location: String,
 
-        /// Method to select the file to keep
+        /// Method to select the file to keep, or a comma separated list of them, most important first
+        /// Example: newest, or prefer:/archive,oldest,shortest-path
         #[arg(short, long, default_value = "newest")]
-        method: DuplicateSelectionMethod,
+        method: KeeperRanking,
 
         // do not create subdirectories in the destination
         #[arg(short, long, default_value = "false")]
@@ -220,10 +625,136 @@ enum Commands {
     },
     #[command(name = "delete", about = "Delete duplicate files")]
     Delete {
-        /// Method to select the file to keep
-        /// Example: newest, oldest, largest, smallest
+        /// Method to select the file to keep, or a comma separated list of them, most important first
+        /// Example: newest, or prefer:/archive,oldest,shortest-path
+        #[arg(short, long, default_value = "newest")]
+        method: KeeperRanking,
+
+        /// Move duplicates to the trash, so they can be restored from a file manager, instead of deleting them
+        #[arg(long, default_value = "false")]
+        to_trash: bool,
+    },
+    #[command(
+        name = "link",
+        about = "Replace duplicate files with hardlinks to the file kept"
+    )]
+    Link {
+        /// Method to select the file to keep, or a comma separated list of them, most important first
+        /// Example: newest, or prefer:/archive,oldest,shortest-path
+        #[arg(short, long, default_value = "newest")]
+        method: KeeperRanking,
+    },
+    #[command(
+        name = "symlink",
+        about = "Replace duplicate files with symbolic links to the file kept"
+    )]
+    Symlink {
+        /// Method to select the file to keep, or a comma separated list of them, most important first
+        /// Example: newest, or prefer:/archive,oldest,shortest-path
+        #[arg(short, long, default_value = "newest")]
+        method: KeeperRanking,
+
+        /// Create links with a target relative to the link, instead of an absolute target
+        #[arg(long, default_value = "false")]
+        relative: bool,
+    },
+    #[command(
+        name = "reflink",
+        about = "Share the storage of duplicate files with the file kept, on filesystems with copy-on-write support"
+    )]
+    Reflink {
+        /// Method to select the file to keep, or a comma separated list of them, most important first
+        /// Example: newest, or prefer:/archive,oldest,shortest-path
         #[arg(short, long, default_value = "newest")]
-        method: DuplicateSelectionMethod,
+        method: KeeperRanking,
+    },
+    #[command(
+        name = "quarantine",
+        about = "Move duplicate files to a quarantine folder, recording where they came from so they can be restored"
+    )]
+    Quarantine {
+        /// The quarantine folder
+        #[arg(short, long)]
+        location: String,
+
+        /// Method to select the file to keep, or a comma separated list of them, most important first
+        /// Example: newest, or prefer:/archive,oldest,shortest-path
+        #[arg(short, long, default_value = "newest")]
+        method: KeeperRanking,
+    },
+    #[command(
+        name = "restore",
+        about = "Move quarantined files back to where they came from"
+    )]
+    Restore {
+        /// The quarantine folder
+        #[arg(short, long)]
+        location: String,
+
+        /// Only restore files whose original path matches this wildcard
+        #[arg(long, value_parser = parse_path_wildcard)]
+        filter: Option<glob::Pattern>,
+    },
+    #[command(name = "purge", about = "Permanently remove quarantined files")]
+    Purge {
+        /// The quarantine folder
+        #[arg(short, long)]
+        location: String,
+
+        /// Only remove files quarantined longer ago than this
+        /// Example: 30d, 12h
+        #[arg(long, default_value = "30d", value_parser = humantime::parse_duration)]
+        older_than: Duration,
+    },
+    #[command(
+        name = "undo",
+        about = "Reverse the changes recorded in a journal, newest first"
+    )]
+    Undo {
+        /// The journal file to undo
+        journal: String,
+    },
+    #[command(
+        name = "apply",
+        about = "Carry out a plan written by --plan-out, skipping files that changed since it was made"
+    )]
+    Apply {
+        /// The plan file to apply
+        plan: String,
+
+        /// Hash each file again before acting on it, as well as checking its size and modified time
+        #[arg(long, default_value = "false")]
+        verify_hash: bool,
+    },
+    #[command(name = "cache", about = "Inspect or maintain the hash cache")]
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+/// # CacheAction
+///
+/// * `Info` - Show where the cache is and what it contains.
+/// * `Prune` - Remove entries for files that no longer exist or have changed.
+/// * `Invalidate` - Remove entries for files under a path, or every entry.
+#[derive(Subcommand, Debug, PartialEq, Clone)]
+enum CacheAction {
+    #[command(
+        name = "info",
+        about = "Show the location and contents of the hash cache"
+    )]
+    Info,
+    #[command(
+        name = "prune",
+        about = "Remove cached hashes of files that no longer exist or have changed"
+    )]
+    Prune,
+    #[command(name = "invalidate", about = "Remove cached hashes")]
+    Invalidate {
+        /// Only remove cached hashes of files under this path
+        #[arg(long)]
+        prefix: Option<String>,
     },
 }
 
@@ -235,12 +766,22 @@ enum Commands {
 /// * `size` - Size of the file in bytes.
 /// * `created_at` - Creation time of the file.
 /// * `modified_at` - Last modified time of the file.
-#[derive(Debug, Clone)]
+/// * `line_endings_normalized` - The file had CRLF line endings normalized when it was hashed.
+/// * `device` - The device the file is stored on (0 where not available).
+/// * `inode` - The inode number of the file (0 where not available).
+/// * `links` - The number of hardlinks to the file, including those outside the search.
+/// * `hardlinks` - Other paths found in the search that are hardlinks to the same file.
+#[derive(Debug, Clone, Default)]
 struct FileInfo {
     path: String,
     size: u64,
     created_at: DateTime<Utc>,
     modified_at: DateTime<Utc>,
+    line_endings_normalized: bool,
+    device: u64,
+    inode: u64,
+    links: u64,
+    hardlinks: Vec<String>,
 }
 
 /// # DuplicateResult
@@ -251,6 +792,11 @@ struct FileInfo {
 /// * `Moved` - the duplicates were moved
 /// * `Found` - the duplicates were found, and left as is
 /// * `Aborted` - user aborted the duplication processing
+/// * `Mismatched` - the duplicates did not match the kept file byte-for-byte, and were left as is
+/// * `Linked` - the duplicates were replaced with hardlinks to the kept file
+/// * `Symlinked` - the duplicates were replaced with symbolic links to the kept file
+/// * `Reflinked` - the duplicates share their storage with the kept file
+/// * `Quarantined` - the duplicates were moved to the quarantine folder
 #[derive(Debug, Clone, PartialEq)]
 enum DuplicateResult {
     Skipped,
@@ -259,59 +805,183 @@ enum DuplicateResult {
     Moved,
     Found,
     Aborted,
+    Mismatched,
+    Linked,
+    Symlinked,
+    Reflinked,
+    Quarantined,
+}
+
+/// # ProcessedFile
+/// What was done to a duplicate file that was processed without error.
+///
+/// * `destination` - Where the file was moved, copied or trashed to, or the file it was linked to.  None for a dry run.
+/// * `bytes_deduplicated` - The number of bytes the filesystem now shares with the kept file, which is 0 unless reflinking.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ProcessedFile {
+    destination: Option<String>,
+    bytes_deduplicated: u64,
+}
+
+/// # FileOutcome
+/// What happened to one duplicate file when it was processed.
+///
+/// * `path` - The path of the file.
+/// * `destination` - Where the file went, or the file it was linked to.
+/// * `error_kind` - The kind of error, if the operation failed.
+/// * `error` - Why the operation failed.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FileOutcome {
+    path: String,
+    destination: Option<String>,
+    error_kind: Option<io::ErrorKind>,
+    error: Option<String>,
+}
+
+impl FileOutcome {
+    /// Record the result of processing a file.
+    fn new(path: &str, result: Result<ProcessedFile, io::Error>) -> FileOutcome {
+        match result {
+            Ok(processed) => FileOutcome {
+                path: path.to_string(),
+                destination: processed.destination,
+                error_kind: None,
+                error: None,
+            },
+            Err(e) => FileOutcome {
+                path: path.to_string(),
+                destination: None,
+                error_kind: Some(e.kind()),
+                error: Some(e.to_string()),
+            },
+        }
+    }
+
+    /// True if the operation failed.
+    fn failed(&self) -> bool {
+        self.error.is_some()
+    }
 }
 
 /// # DuplicateFileSet
 ///
 /// Struct representing a set of duplicate files.
 ///
+/// * `hash` - The hash shared by the files.
 /// * `keeper` - The file to keep.
 /// * `extras` - The duplicate files.
 /// * `result` - What happened to the duplicate files
+/// * `normalized_match` - The files only match once line endings are normalized, so they may not be byte-identical
+/// * `algorithm` - The algorithm used to calculate the hash
+/// * `bytes_deduplicated` - The number of bytes the filesystem now shares between the files
+/// * `outcomes` - What happened to each duplicate, and its hardlinks, that was processed
+/// * `reason` - Why the file kept was changed from the one selected, or why the duplicates were left alone
 #[derive(Debug, Clone)]
 struct DuplicateFileSet {
     hash: String,
+    algorithm: HashAlgorithm,
     keeper: Option<FileInfo>,
     extras: Vec<FileInfo>,
     result: DuplicateResult,
+    normalized_match: bool,
+    bytes_deduplicated: u64,
+    outcomes: Vec<FileOutcome>,
+    reason: Option<String>,
 }
 
 /// # SearchResults
 /// Struct representing the search results.
 /// * `number_duplicates` - The number of duplicate sets found.
-/// * `total_size` - The total size of the duplicates found.
+/// * `total_size` - The total size of the duplicates found that can be reclaimed.
+/// * `bytes_deduplicated` - The number of bytes deduplicated by the filesystem.
+/// * `failures` - The files the action failed on.
 #[derive(Debug, Clone)]
 struct SearchResults {
     number_duplicates: usize,
     total_size: usize,
+    bytes_deduplicated: u64,
+    failures: Vec<FileOutcome>,
+}
+
+impl SearchResults {
+    /// The status to exit with, so failures are not mistaken for finding duplicates.
+    fn exit_code(&self) -> ExitCode {
+        if !self.failures.is_empty() {
+            ExitCode::CompletedWithErrors
+        } else if self.number_duplicates > 0 {
+            ExitCode::DuplicatesFound
+        } else {
+            ExitCode::NoDuplicates
+        }
+    }
+}
+
+/// # ExitCode
+/// The status the program exits with, in the style of `diff` and `grep`.
+/// * `NoDuplicates` - The search completed and found no duplicates, or a command that does not search succeeded.
+/// * `DuplicatesFound` - The search completed and found duplicates.
+/// * `CompletedWithErrors` - The run completed, but failed on some files.
+/// * `UsageError` - The command line arguments were invalid, or a plan had no actions to apply.
+/// * `IoError` - An error stopped the run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExitCode {
+    NoDuplicates = 0,
+    DuplicatesFound = 1,
+    CompletedWithErrors = 2,
+    UsageError = 3,
+    IoError = 4,
 }
 
 /// # FileOperations
 /// Trait for file operations such as copy, move, and delete.
-/// * `copy` - Copy a file from source to destination.
-/// * `remove_file` - Remove a file.
-/// * `rename` - Rename a file.
+/// * `copy` - Copy a file from source to destination, returning where it was copied to.
+/// * `remove_file` - Remove a file, returning where it was moved to if it can be recovered.
+/// * `rename` - Rename a file, returning where it was moved to.
+/// * `hard_link` - Replace the destination with a hardlink to the source.
+/// * `symlink` - Replace the destination with a symbolic link to the source.
+/// * `reflink` - Share the storage of the source with the destination, returning the bytes deduplicated.
 trait FileOperations {
-    fn copy(&self, source: &str, destination: &str, overwrite: bool) -> Result<(), std::io::Error>;
-    fn remove_file(&self, source: &str) -> Result<(), std::io::Error>;
+    fn copy(
+        &self,
+        source: &str,
+        destination: &str,
+        overwrite: bool,
+    ) -> Result<String, std::io::Error>;
+    fn remove_file(&self, source: &str) -> Result<Option<String>, std::io::Error>;
     fn rename(
         &self,
         source: &str,
         destination: &str,
         overwrite: bool,
+    ) -> Result<String, std::io::Error>;
+    fn hard_link(&self, source: &str, destination: &str) -> Result<(), std::io::Error>;
+    fn symlink(
+        &self,
+        source: &str,
+        destination: &str,
+        relative: bool,
     ) -> Result<(), std::io::Error>;
+    fn reflink(&self, source: &str, destination: &str) -> Result<u64, std::io::Error>;
 }
 
 /// # RealFileOperations
 /// Implementation of `FileOperations` for real file operations.
-/// * `copy` - Copy a file from source to destination.
-/// * `remove_file` - Remove a file.
-/// * `rename` - Rename a file.
+/// * `copy` - Copy a file from source to destination, returning where it was copied to.
+/// * `remove_file` - Remove a file permanently.
+/// * `rename` - Rename a file, returning where it was moved to.
+/// * `hard_link` - Replace the destination with a hardlink to the source.
+/// * `symlink` - Replace the destination with a symbolic link to the source.
+/// * `reflink` - Share the storage of the source with the destination, returning the bytes deduplicated.
 struct RealFileOperations;
 
 impl FileOperations for RealFileOperations {
     #[cfg(not(tarpaulin_include))]
-    fn copy(&self, source: &str, destination: &str, overwrite: bool) -> Result<(), std::io::Error> {
+    fn copy(
+        &self,
+        source: &str,
+        destination: &str,
+        overwrite: bool,
+    ) -> Result<String, std::io::Error> {
         let mut counter = 1;
         let mut new_destination = destination.to_string();
         // if overwrite is false,
@@ -339,14 +1009,14 @@ impl FileOperations for RealFileOperations {
         }
         // copy the file
         match std::fs::copy(source, &new_destination) {
-            Ok(_) => Ok(()),
+            Ok(_) => Ok(new_destination),
             Err(e) => Err(e),
         }
     }
     #[cfg(not(tarpaulin_include))]
-    fn remove_file(&self, source: &str) -> Result<(), std::io::Error> {
+    fn remove_file(&self, source: &str) -> Result<Option<String>, std::io::Error> {
         match std::fs::remove_file(source) {
-            Ok(_) => Ok(()),
+            Ok(_) => Ok(None),
             Err(e) => Err(e),
         }
     }
@@ -356,7 +1026,7 @@ impl FileOperations for RealFileOperations {
         source: &str,
         destination: &str,
         overwrite: bool,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<String, std::io::Error> {
         let mut counter = 1;
         let mut new_destination = destination.to_string();
         // if overwrite is false,
@@ -382,11 +1052,166 @@ impl FileOperations for RealFileOperations {
                 }
             }
         }
-        match std::fs::rename(source, new_destination) {
-            Ok(_) => Ok(()),
+        match std::fs::rename(source, &new_destination) {
+            Ok(_) => Ok(new_destination),
             Err(e) => Err(e),
         }
     }
+    #[cfg(not(tarpaulin_include))]
+    fn hard_link(&self, source: &str, destination: &str) -> Result<(), std::io::Error> {
+        ensure_different_files(Path::new(source), Path::new(destination))?;
+
+        // hardlinks can not cross filesystems
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let source_device = std::fs::metadata(source)?.dev();
+            let destination_folder = Path::new(destination)
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            if std::fs::metadata(destination_folder)?.dev() != source_device {
+                return Err(io::Error::other(format!(
+                    "{} and {} are on different filesystems",
+                    source, destination
+                )));
+            }
+        }
+
+        // link to a temporary name next to the destination, then rename it over the destination
+        // so the destination is replaced atomically
+        let destination_path = Path::new(destination);
+        let file_name = destination_path
+            .file_name()
+            .ok_or_else(|| io::Error::other(format!("Invalid destination: {}", destination)))?;
+        let temp_path = destination_path
+            .with_file_name(format!(".{}.dupefindr-link", file_name.to_string_lossy()));
+        std::fs::hard_link(source, &temp_path)?;
+        if let Err(e) = std::fs::rename(&temp_path, destination_path) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        Ok(())
+    }
+    #[cfg(not(tarpaulin_include))]
+    fn symlink(
+        &self,
+        source: &str,
+        destination: &str,
+        relative: bool,
+    ) -> Result<(), std::io::Error> {
+        let destination_path = Path::new(destination);
+
+        // work out the link target before anything is changed
+        let source_path = std::fs::canonicalize(source)?;
+        ensure_different_files(&source_path, destination_path)?;
+        let target = if relative {
+            let destination_folder = destination_path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            relative_path(&std::fs::canonicalize(destination_folder)?, &source_path)
+        } else {
+            source_path
+        };
+
+        // move the original out of the way, so it can be restored if the link can not be created
+        replace_with_backup(destination_path, |destination_path| {
+            #[cfg(unix)]
+            let result = std::os::unix::fs::symlink(&target, destination_path);
+            #[cfg(windows)]
+            let result = std::os::windows::fs::symlink_file(&target, destination_path);
+            result
+        })
+    }
+    #[cfg(not(tarpaulin_include))]
+    fn reflink(&self, source: &str, destination: &str) -> Result<u64, std::io::Error> {
+        let source_file = fs::File::open(source)?;
+        let destination_file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(destination)?;
+        let length = source_file.metadata()?.len();
+        if destination_file.metadata()?.len() != length {
+            return Err(io::Error::other(format!(
+                "{} and {} are different sizes",
+                source, destination
+            )));
+        }
+        reflink::dedupe_files(&source_file, &destination_file, length)
+    }
+}
+
+/// # ensure_different_files
+/// Make sure a link would not replace the file it links to, since that would remove the only copy.
+///
+/// * `source` - The file linked to.
+/// * `destination` - The file to replace with the link.
+fn ensure_different_files(source: &Path, destination: &Path) -> Result<(), std::io::Error> {
+    if std::fs::canonicalize(source)? == std::fs::canonicalize(destination)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} and {} are the same file",
+                source.display(),
+                destination.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// # replace_with_backup
+/// Replace a file with whatever `create` makes at its path.  The original is moved out of the way first,
+/// and put back if `create` fails.
+///
+/// * `destination` - The file to replace.
+/// * `create` - Creates the replacement at the path it is given.
+fn replace_with_backup<F>(destination: &Path, create: F) -> Result<(), std::io::Error>
+where
+    F: FnOnce(&Path) -> Result<(), std::io::Error>,
+{
+    let file_name = destination.file_name().ok_or_else(|| {
+        io::Error::other(format!("Invalid destination: {}", destination.display()))
+    })?;
+    // never replace another file with the backup
+    let backup_path = quarantine::reserve_unused_path(
+        &destination.with_file_name(format!(".{}.dupefindr-backup", file_name.to_string_lossy())),
+    )?;
+    if let Err(e) = std::fs::rename(destination, &backup_path) {
+        let _ = std::fs::remove_file(&backup_path);
+        return Err(e);
+    }
+    if let Err(e) = create(destination) {
+        // remove anything left at the destination, so the original can be put back
+        let _ = std::fs::remove_file(destination);
+        let _ = std::fs::rename(&backup_path, destination);
+        return Err(e);
+    }
+    std::fs::remove_file(&backup_path)
+}
+
+/// # relative_path
+/// Get the path to a target, relative to a folder.  Both paths must be absolute.
+///
+/// * `from` - The folder the path is relative to.
+/// * `to` - The target of the path.
+fn relative_path(from: &Path, to: &Path) -> std::path::PathBuf {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut path = std::path::PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    for component in &to[common..] {
+        path.push(component);
+    }
+    path
 }
 
 /// # TerminalGuard
@@ -413,27 +1238,104 @@ fn main() {
 
     // we need to test if the command line args passed in were valid
     // if they aren't then have print the error and exit
-    let args = match Args::try_parse() {
+    let mut args = match Args::try_parse() {
         Ok(args) => args,
         Err(e) => {
             println!("{}", e);
             println!();
-            std::process::exit(-1);
+            // help and version are not errors
+            let code = if e.use_stderr() {
+                ExitCode::UsageError
+            } else {
+                ExitCode::NoDuplicates
+            };
+            std::process::exit(code as i32);
         }
     };
 
+    if let Err(e) = load_config(&mut args) {
+        eprintln!("Error: {}", e);
+        // a setting that is not valid is as much a usage error as an invalid argument
+        let code = if e.kind() == io::ErrorKind::InvalidData {
+            ExitCode::UsageError
+        } else {
+            ExitCode::IoError
+        };
+        std::process::exit(code as i32);
+    }
+
     setup_terminal();
 
     print_banner();
 
     if get_command_line_arguments(&args).is_err() {
         reset_terminal();
-        std::process::exit(-1);
+        std::process::exit(ExitCode::UsageError as i32);
     }
 
     //setup_ctrlc_handler();
 
-    match start_search(&file_ops, &args) {
+    // the cache command maintains the hash cache, rather than searching for duplicates
+    if let Commands::Cache { action } = &args.command {
+        let code = match run_cache_command(&args, action) {
+            Ok(()) => ExitCode::NoDuplicates,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::IoError
+            }
+        };
+        reset_terminal();
+        std::process::exit(code as i32);
+    }
+
+    // undo reverses an earlier run, rather than searching for duplicates
+    if let Commands::Undo { journal } = &args.command {
+        let code = match run_undo_command(&args, journal) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::IoError
+            }
+        };
+        reset_terminal();
+        std::process::exit(code as i32);
+    }
+
+    // apply carries out a plan made by an earlier search
+    if let Commands::Apply { plan, verify_hash } = &args.command {
+        let code = match run_apply_command(&file_ops, &args, plan, *verify_hash) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::IoError
+            }
+        };
+        reset_terminal();
+        std::process::exit(code as i32);
+    }
+
+    // restore and purge maintain a quarantine folder, rather than searching for duplicates
+    if let Commands::Restore { .. } | Commands::Purge { .. } = &args.command {
+        let code = match run_quarantine_command(&args) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::IoError
+            }
+        };
+        reset_terminal();
+        std::process::exit(code as i32);
+    }
+
+    // deleting to the trash swaps in the trash file operations
+    let search = match &args.command {
+        Commands::Delete { to_trash: true, .. } => match trash::default_home_trash() {
+            Some(home_trash) => start_search(&TrashFileOperations::new(&home_trash), &args),
+            None => Err(io::Error::other("Unable to locate the trash folder")),
+        },
+        _ => start_search(&file_ops, &args),
+    };
+    match search {
         Ok(search_results) => {
             let duration = start.elapsed();
             println!("Elapsed time: {}", humantime::format_duration(duration));
@@ -445,16 +1347,28 @@ fn main() {
                     search_results.number_duplicates,
                     bytesize::ByteSize(search_results.total_size.try_into().unwrap())
                 );
+                if let Commands::Reflink { .. } = args.command {
+                    println!(
+                        "Deduplicated {} with the filesystem",
+                        bytesize::ByteSize(search_results.bytes_deduplicated)
+                    );
+                }
                 println!();
                 println!();
             }
+            if !search_results.failures.is_empty() {
+                println!(
+                    "Completed with errors: {} files could not be processed",
+                    search_results.failures.len()
+                );
+            }
             reset_terminal();
-            std::process::exit(search_results.number_duplicates.try_into().unwrap());
+            std::process::exit(search_results.exit_code() as i32);
         }
         Err(e) => {
             eprintln!("Error: {}", e);
             reset_terminal();
-            std::process::exit(-1)
+            std::process::exit(ExitCode::IoError as i32)
         }
     }
 }
@@ -510,6 +1424,41 @@ fn get_command_line_arguments(args: &Args) -> Result<(), std::io::Error> {
         println!("Available cpus: {}", default_parallelism_approx);
         println!("Create Report: {}", args.shared.create_report);
         println!("Report Path: {}", args.shared.report_path);
+        println!("Report Format: {:?}", args.shared.report_format);
+        println!(
+            "Database: {}",
+            args.shared.db.as_deref().unwrap_or_default()
+        );
+        println!("Prefer: {:?}", args.shared.prefer);
+        println!("Avoid: {:?}", args.shared.avoid);
+        println!("Protect: {:?}", args.shared.protect);
+        println!(
+            "Partial hash head: {} KiB",
+            args.shared.partial_hash_head_kib
+        );
+        println!(
+            "Partial hash tail: {} KiB",
+            args.shared.partial_hash_tail_kib
+        );
+        println!("Verify: {}", should_verify(args));
+        println!(
+            "Normalize line endings: {}",
+            args.shared.normalize_line_endings
+        );
+        println!("Hash algorithm: {}", args.shared.hash_algorithm);
+        println!("No cache: {}", args.shared.no_cache);
+        println!("List hardlinks: {}", args.shared.list_hardlinks);
+        println!("No journal: {}", args.shared.no_journal);
+        println!(
+            "Plan out: {}",
+            args.shared.plan_out.as_deref().unwrap_or_default()
+        );
+        println!(
+            "Cache path: {}",
+            get_cache_path(args)
+                .map(|path| path.display().to_string())
+                .unwrap_or_default()
+        );
         println!();
     }
 
@@ -541,22 +1490,554 @@ fn get_number_of_threads(args: &Args) -> usize {
     }
 }
 
-/// # start_search
-/// Start the search for duplicate files.
-/// * `file_ops` - The file operations object.
+/// # get_cache_path
+/// Get the path of the hash cache, from the command line or the default location
 /// * `args` - The command line arguments.
-/// * `running` - The running flag.
-/// * `Result<SearchResults, io::Error>` - The search results.
-/// # Errors
-/// * `io::Error` - An error occurred during the search.
-fn start_search<T: FileOperations>(file_ops: &T, args: &Args) -> Result<SearchResults, io::Error> {
-    // get the files in the directory
-    let folder_path: String = args.shared.path.clone();
+/// * `Option<PathBuf>` - The path of the cache, or None if there is no default location.
+fn get_cache_path(args: &Args) -> Option<PathBuf> {
+    match &args.shared.cache_path {
+        Some(cache_path) => Some(PathBuf::from(cache_path)),
+        None => cache::default_cache_path(),
+    }
+}
 
-    // get the files in the directory
-    // it calls itself as it traverses the tree if recursive is set
-    let multi = MultiProgress::new();
-    let result = get_files_in_directory(args, folder_path, &multi, true);
+/// # open_hash_cache
+/// Open the hash cache, unless it is disabled.
+/// A cache that can not be read is reported and replaced with an empty cache.
+/// * `args` - The command line arguments.
+/// * `Option<HashCache>` - The hash cache, or None if it is disabled.
+fn open_hash_cache(args: &Args) -> Option<HashCache> {
+    if args.shared.no_cache {
+        return None;
+    }
+    let cache_path = get_cache_path(args)?;
+    match HashCache::load(&cache_path) {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            eprintln!(
+                "Ignoring unreadable hash cache {}: {}",
+                cache_path.display(),
+                e
+            );
+            Some(HashCache::new(&cache_path))
+        }
+    }
+}
+
+/// # open_journal
+/// Open the journal for this run, unless nothing will be changed or it is disabled.
+/// * `args` - The command line arguments.
+/// * `Option<Journal>` - The journal, or None if nothing will be recorded.
+fn open_journal(args: &Args) -> Option<Journal> {
+    if args.shared.no_journal || args.shared.dry_run || args.shared.plan_out.is_some() {
+        return None;
+    }
+    if let Commands::Find { .. } = args.command {
+        return None;
+    }
+    match &args.shared.journal {
+        Some(journal) => Some(Journal::new(Path::new(journal))),
+        None => journal::default_journal_path().map(|path| Journal::new(&path)),
+    }
+}
+
+/// # run_undo_command
+/// Reverse the changes recorded in a journal.
+/// * `args` - The command line arguments.
+/// * `journal` - The path of the journal.
+/// * `Result<ExitCode, io::Error>` - CompletedWithErrors if any change could not be undone.
+/// # Errors
+/// * `io::Error` - The journal could not be read.
+fn run_undo_command(args: &Args, journal: &str) -> Result<ExitCode, io::Error> {
+    let entries = Journal::load(Path::new(journal))?;
+    let summary = journal::undo(&entries, args.shared.dry_run);
+    for error in &summary.errors {
+        eprintln!("{}", error);
+    }
+    println!(
+        "{}Undone {} of {} changes from {}",
+        if args.shared.dry_run { "Dry run: " } else { "" },
+        summary.undone,
+        entries.len(),
+        journal
+    );
+    Ok(match summary.errors.is_empty() {
+        true => ExitCode::NoDuplicates,
+        false => ExitCode::CompletedWithErrors,
+    })
+}
+
+/// # run_cache_command
+/// Inspect or maintain the hash cache
+/// * `args` - The command line arguments.
+/// * `action` - What to do with the cache.
+/// # Errors
+/// * `io::Error` - The cache could not be read or written.
+fn run_cache_command(args: &Args, action: &CacheAction) -> Result<(), io::Error> {
+    let cache_path = get_cache_path(args)
+        .ok_or_else(|| io::Error::other("Unable to determine the location of the hash cache"))?;
+    let mut cache = HashCache::load(&cache_path)?;
+    match action {
+        CacheAction::Info => {
+            println!("Cache path: {}", cache.path().display());
+            println!("Cached hashes: {}", cache.len());
+            for (algorithm, count) in cache.count_by_algorithm() {
+                println!("  {}: {}", algorithm, count);
+            }
+            if let Ok(metadata) = std::fs::metadata(cache.path()) {
+                println!("Cache size: {}", bytesize::ByteSize(metadata.len()));
+            }
+        }
+        CacheAction::Prune => {
+            let removed = cache.prune();
+            cache.save()?;
+            println!("Removed {} stale cached hashes", removed);
+        }
+        CacheAction::Invalidate { prefix } => {
+            let removed = cache.invalidate(prefix.as_deref());
+            cache.save()?;
+            println!("Removed {} cached hashes", removed);
+        }
+    }
+    Ok(())
+}
+
+/// # run_quarantine_command
+/// Run the restore or purge command against a quarantine folder.
+/// * `args` - The command line arguments.
+/// * `Result<ExitCode, io::Error>` - CompletedWithErrors if any file could not be restored or purged.
+/// # Errors
+/// * `io::Error` - The manifest could not be read or written.
+fn run_quarantine_command(args: &Args) -> Result<ExitCode, io::Error> {
+    let (location, summary, remaining, action) = match &args.command {
+        Commands::Restore { location, filter } => {
+            let mut manifest = Manifest::load(Path::new(location))?;
+            let summary = manifest.restore(filter.as_ref(), args.shared.dry_run);
+            if !args.shared.dry_run {
+                manifest.save()?;
+            }
+            (location, summary, manifest.entries().len(), "Restored")
+        }
+        Commands::Purge {
+            location,
+            older_than,
+        } => {
+            let cutoff =
+                Utc::now() - chrono::Duration::from_std(*older_than).map_err(io::Error::other)?;
+            let mut manifest = Manifest::load(Path::new(location))?;
+            let summary = manifest.purge(cutoff, args.shared.dry_run);
+            if !args.shared.dry_run {
+                manifest.save()?;
+            }
+            (location, summary, manifest.entries().len(), "Purged")
+        }
+        _ => return Ok(ExitCode::NoDuplicates),
+    };
+    for error in &summary.errors {
+        eprintln!("{}", error);
+    }
+    println!(
+        "{}{} {} files ({}) from {}",
+        if args.shared.dry_run { "Dry run: " } else { "" },
+        action,
+        summary.files,
+        bytesize::ByteSize(summary.size),
+        location
+    );
+    println!("{} files remain in quarantine", remaining);
+    Ok(match summary.errors.is_empty() {
+        true => ExitCode::NoDuplicates,
+        false => ExitCode::CompletedWithErrors,
+    })
+}
+
+/// # run_apply_command
+/// Carry out a plan written by an earlier search.
+/// * `file_ops` - The file operations object.
+/// * `args` - The command line arguments.
+/// * `plan_path` - The path of the plan.
+/// * `verify_hash` - Hash each file again before acting on it.
+/// * `Result<ExitCode, io::Error>` - CompletedWithErrors if the action failed on any file.
+/// # Errors
+/// * `io::Error` - The plan could not be read.
+fn run_apply_command<T: FileOperations>(
+    file_ops: &T,
+    args: &Args,
+    plan_path: &str,
+    verify_hash: bool,
+) -> Result<ExitCode, io::Error> {
+    let plan = Plan::load(Path::new(plan_path))?;
+    // a plan made by find, or edited to leave every set alone, would silently do nothing
+    if !plan.sets.is_empty() && !plan.has_actions() {
+        eprintln!(
+            "The action of every set in {} is none, so there is nothing to apply. \
+             Make the plan with the command to carry out, such as delete --plan-out, or edit the action of its sets",
+            plan_path
+        );
+        return Ok(ExitCode::UsageError);
+    }
+    let summary = apply_plan(file_ops, args, &plan, verify_hash);
+    println!(
+        "{}Applied {} changes from {}",
+        if args.shared.dry_run { "Dry run: " } else { "" },
+        summary.applied,
+        plan_path
+    );
+    if summary.skipped > 0 {
+        println!(
+            "Skipped {} files that changed since the plan was made",
+            summary.skipped
+        );
+    }
+    if summary.failed > 0 {
+        println!("Failed to apply {} changes", summary.failed);
+        return Ok(ExitCode::CompletedWithErrors);
+    }
+    Ok(ExitCode::NoDuplicates)
+}
+
+/// # planned_action
+/// The action a plan records for a command.
+/// * `command` - The command the plan was made with.
+/// * `PlanAction` - What will be done with the duplicates.
+fn planned_action(command: &Commands) -> PlanAction {
+    match command {
+        Commands::Move {
+            location,
+            flatten,
+            no_hash_folder,
+            overwrite,
+            ..
+        } => PlanAction::Move {
+            location: location.clone(),
+            flatten: *flatten,
+            no_hash_folder: *no_hash_folder,
+            overwrite: *overwrite,
+        },
+        Commands::Copy {
+            location,
+            flatten,
+            no_hash_folder,
+            overwrite,
+            ..
+        } => PlanAction::Copy {
+            location: location.clone(),
+            flatten: *flatten,
+            no_hash_folder: *no_hash_folder,
+            overwrite: *overwrite,
+        },
+        Commands::Delete { to_trash, .. } => PlanAction::Delete {
+            to_trash: *to_trash,
+        },
+        Commands::Link { .. } => PlanAction::Link,
+        Commands::Symlink { relative, .. } => PlanAction::Symlink {
+            relative: *relative,
+        },
+        Commands::Reflink { .. } => PlanAction::Reflink,
+        Commands::Quarantine { location, .. } => PlanAction::Quarantine {
+            location: location.clone(),
+        },
+        Commands::Find { .. }
+        | Commands::Cache { .. }
+        | Commands::Restore { .. }
+        | Commands::Purge { .. }
+        | Commands::Undo { .. }
+        | Commands::Apply { .. } => PlanAction::None,
+    }
+}
+
+/// # planned_command
+/// The command that carries out a planned action.
+/// * `action` - What will be done with the duplicates.
+/// * `Option<Commands>` - The command, or None if the duplicates are left alone.
+fn planned_command(action: &PlanAction) -> Option<Commands> {
+    // the plan has already selected the file to keep
+    let method: KeeperRanking = DuplicateSelectionMethod::Newest.into();
+    match action {
+        PlanAction::None => None,
+        PlanAction::Move {
+            location,
+            flatten,
+            no_hash_folder,
+            overwrite,
+        } => Some(Commands::Move {
+            location: location.clone(),
+            method,
+            flatten: *flatten,
+            no_hash_folder: *no_hash_folder,
+            overwrite: *overwrite,
+        }),
+        PlanAction::Copy {
+            location,
+            flatten,
+            no_hash_folder,
+            overwrite,
+        } => Some(Commands::Copy {
+            location: location.clone(),
+            method,
+            flatten: *flatten,
+            no_hash_folder: *no_hash_folder,
+            overwrite: *overwrite,
+        }),
+        PlanAction::Delete { to_trash } => Some(Commands::Delete {
+            method,
+            to_trash: *to_trash,
+        }),
+        PlanAction::Link => Some(Commands::Link { method }),
+        PlanAction::Symlink { relative } => Some(Commands::Symlink {
+            method,
+            relative: *relative,
+        }),
+        PlanAction::Reflink => Some(Commands::Reflink { method }),
+        PlanAction::Quarantine { location } => Some(Commands::Quarantine {
+            location: location.clone(),
+            method,
+        }),
+    }
+}
+
+/// # create_plan
+/// Record the duplicates found and the action to take on them as a plan.
+/// Sets that were skipped, aborted or did not match byte-for-byte are left out.
+/// * `args` - The command line arguments.
+/// * `dup_fileset_vec` - The duplicate sets.
+/// * `Plan` - The plan.
+fn create_plan(args: &Args, dup_fileset_vec: &[DuplicateFileSet]) -> Plan {
+    let planned_file = |file: &FileInfo| PlannedFile {
+        path: file.path.clone(),
+        size: file.size,
+        created_at: file.created_at,
+        modified_at: file.modified_at,
+        hardlinks: file.hardlinks.clone(),
+    };
+    let action = planned_action(&args.command);
+    let sets = dup_fileset_vec
+        .iter()
+        .filter(|dup_fileset| {
+            !matches!(
+                dup_fileset.result,
+                DuplicateResult::Mismatched | DuplicateResult::Skipped | DuplicateResult::Aborted
+            )
+        })
+        .filter_map(|dup_fileset| {
+            dup_fileset.keeper.as_ref().map(|keeper| PlannedSet {
+                hash: dup_fileset.hash.clone(),
+                action: action.clone(),
+                keeper: planned_file(keeper),
+                extras: dup_fileset.extras.iter().map(planned_file).collect(),
+            })
+        })
+        .collect();
+    Plan {
+        version: plan::PLAN_VERSION,
+        created_at: Utc::now(),
+        root: args.shared.path.clone(),
+        algorithm: args.shared.hash_algorithm,
+        normalize_line_endings: args.shared.normalize_line_endings,
+        sets,
+    }
+}
+
+/// # apply_plan
+/// Carry out a plan.  Each file is checked against the size and modified time recorded in the plan,
+/// and optionally its hash, and left alone if it has changed.
+/// * `file_ops` - The file operations object.
+/// * `args` - The command line arguments.
+/// * `plan` - The plan.
+/// * `verify_hash` - Hash each file again before acting on it.
+/// * `ApplySummary` - The number of files acted on, skipped and failed.
+fn apply_plan<T: FileOperations>(
+    file_ops: &T,
+    args: &Args,
+    plan: &Plan,
+    verify_hash: bool,
+) -> ApplySummary {
+    let mut summary = ApplySummary::default();
+    let mut multi = MultiProgress::new();
+    let journal = open_journal(args);
+    for set in &plan.sets {
+        let Some(command) = planned_command(&set.action) else {
+            continue;
+        };
+        // act as the search that made the plan would have
+        let set_args = Args {
+            shared: SharedOptions {
+                path: plan.root.clone(),
+                hash_algorithm: plan.algorithm,
+                normalize_line_endings: plan.normalize_line_endings,
+                ..args.shared.clone()
+            },
+            command,
+        };
+        match set_args.command {
+            Commands::Delete { to_trash: true, .. } => match trash::default_home_trash() {
+                Some(home_trash) => apply_planned_set(
+                    &TrashFileOperations::new(&home_trash),
+                    &set_args,
+                    set,
+                    verify_hash,
+                    journal.as_ref(),
+                    &mut multi,
+                    &mut summary,
+                ),
+                None => {
+                    let _ = multi.println("*** Unable to locate the trash folder");
+                    summary.failed += set.extras.len();
+                }
+            },
+            _ => apply_planned_set(
+                file_ops,
+                &set_args,
+                set,
+                verify_hash,
+                journal.as_ref(),
+                &mut multi,
+                &mut summary,
+            ),
+        }
+    }
+    if let Some(journal) = journal.filter(|journal| journal.is_written()) {
+        println!(
+            "Changes recorded in journal: {} (use the undo command to reverse them)",
+            journal.path().display()
+        );
+    }
+    summary
+}
+
+/// # apply_planned_set
+/// Carry out the action of one set of a plan.
+/// * `file_ops` - The file operations object.
+/// * `args` - The command line arguments, with the command of the planned action.
+/// * `set` - The set of duplicates.
+/// * `verify_hash` - Hash each file again before acting on it.
+/// * `journal` - The journal to record the operations in, if there is one.
+/// * `multi` - The progress bar.
+/// * `summary` - Counts of the files acted on, skipped and failed.
+fn apply_planned_set<T: FileOperations>(
+    file_ops: &T,
+    args: &Args,
+    set: &PlannedSet,
+    verify_hash: bool,
+    journal: Option<&Journal>,
+    multi: &mut MultiProgress,
+    summary: &mut ApplySummary,
+) {
+    let file_info = |file: &PlannedFile| FileInfo {
+        path: file.path.clone(),
+        size: file.size,
+        created_at: file.created_at,
+        modified_at: file.modified_at,
+        hardlinks: file.hardlinks.clone(),
+        ..Default::default()
+    };
+
+    // the duplicates are compared with, and may be linked to, the keeper so it must not have changed either
+    if let Err(reason) = check_planned_file(args, &set.keeper, &set.hash, verify_hash) {
+        let _ = multi.println(format!(
+            "*** Skipping {} duplicates of {}: {}",
+            set.extras.len(),
+            set.keeper.path,
+            reason
+        ));
+        summary.skipped += set.extras.len();
+        return;
+    }
+    let keeper = file_info(&set.keeper);
+
+    let mut extras: Vec<FileInfo> = Vec::new();
+    for extra in &set.extras {
+        match check_planned_file(args, extra, &set.hash, verify_hash) {
+            Ok(()) => extras.push(file_info(extra)),
+            Err(reason) => {
+                let _ = multi.println(format!("*** Skipping {}: {}", extra.path, reason));
+                summary.skipped += 1;
+            }
+        }
+    }
+    if should_verify(args) {
+        let (verified, mismatched) =
+            verify_duplicates(&keeper, &extras, args.shared.normalize_line_endings, multi);
+        summary.skipped += mismatched.len();
+        extras = verified;
+    }
+
+    for file in &extras {
+        let mut paths = vec![file.clone()];
+        // hardlinks share the storage of the duplicate, so reflinking it covers them too
+        if !matches!(args.command, Commands::Reflink { .. }) {
+            paths.extend(file.hardlinks.iter().map(|link| FileInfo {
+                path: link.clone(),
+                hardlinks: vec![],
+                ..file.clone()
+            }));
+        }
+        for path in &paths {
+            match process_a_duplicate_file(
+                file_ops,
+                args,
+                path,
+                Some(&keeper),
+                journal,
+                &set.hash,
+                multi,
+            ) {
+                Ok(_) => summary.applied += 1,
+                Err(_) => summary.failed += 1,
+            }
+        }
+    }
+}
+
+/// # check_planned_file
+/// Check that a file has not changed since the plan was made.
+/// * `args` - The command line arguments, with the hash settings of the plan.
+/// * `file` - The file as it was when the plan was made.
+/// * `hash` - The hash of the file when the plan was made.
+/// * `verify_hash` - Hash the file again, as well as checking its size and modified time.
+/// * `Result<(), String>` - The reason the file has changed.
+fn check_planned_file(
+    args: &Args,
+    file: &PlannedFile,
+    hash: &str,
+    verify_hash: bool,
+) -> Result<(), String> {
+    file.check()?;
+    if !verify_hash {
+        return Ok(());
+    }
+    let current = if args.shared.normalize_line_endings {
+        get_normalized_hash_of_file(&file.path, args.shared.hash_algorithm).map(|(hash, _)| hash)
+    } else {
+        get_hash_of_file(
+            &file.path,
+            args.shared.hash_algorithm,
+            &ProgressBar::hidden(),
+        )
+    };
+    match current {
+        Ok(current) if current == hash => Ok(()),
+        Ok(_) => Err(format!("{} no longer has hash {}", file.path, hash)),
+        Err(e) => Err(format!("unable to hash {}: {}", file.path, e)),
+    }
+}
+
+/// # start_search
+/// Start the search for duplicate files.
+/// * `file_ops` - The file operations object.
+/// * `args` - The command line arguments.
+/// * `running` - The running flag.
+/// * `Result<SearchResults, io::Error>` - The search results.
+/// # Errors
+/// * `io::Error` - An error occurred during the search.
+fn start_search<T: FileOperations>(file_ops: &T, args: &Args) -> Result<SearchResults, io::Error> {
+    // get the files in the directory
+    let folder_path: String = args.shared.path.clone();
+
+    // get the files in the directory
+    // it calls itself as it traverses the tree if recursive is set
+    let multi = MultiProgress::new();
+    let result = get_files_in_directory(args, folder_path, &multi, true);
     let files = match result {
         Ok(files) => files,
         Err(e) => {
@@ -568,20 +2049,73 @@ fn start_search<T: FileOperations>(file_ops: &T, args: &Args) -> Result<SearchRe
         println!("Found {} files", files.len());
     }
 
+    // hardlinks to the same file are one logical file, so they are not duplicates of each other
+    let (files, collapsed) = collapse_hardlinks(files);
+    if args.shared.verbose && collapsed > 0 {
+        println!("Collapsed {} hardlinks to files already found", collapsed);
+    }
+    if args.shared.list_hardlinks {
+        for file in files.iter().filter(|file| !file.hardlinks.is_empty()) {
+            println!("Already linked: {}", file.path);
+            for link in &file.hardlinks {
+                println!("  {}", link);
+            }
+        }
+    }
+
     // identify the duplicates
     let full_hash_map = identify_duplicates(args, files);
     // process the duplicates
     let dup_fileset_vec = process_duplicates(file_ops, args, &full_hash_map);
 
     // print the duplicate results
-    let duplicates_found = dup_fileset_vec.len();
+    let mut duplicates_found = 0;
     let mut duplicates_total_size: i64 = 0;
+    let mut bytes_deduplicated: u64 = 0;
     for dup_fileset in dup_fileset_vec.iter() {
+        // files that failed verification are not duplicates, so they are not counted
+        if dup_fileset.result == DuplicateResult::Mismatched {
+            println!(
+                "{} files did not match {} byte-for-byte and were skipped",
+                dup_fileset.extras.len(),
+                dup_fileset
+                    .keeper
+                    .as_ref()
+                    .map(|keeper| keeper.path.as_str())
+                    .unwrap_or_default()
+            );
+            continue;
+        }
+        duplicates_found += 1;
+        // sets skipped by the rules free no space
+        if let Some(reason) = &dup_fileset.reason {
+            if dup_fileset.result == DuplicateResult::Skipped {
+                println!(
+                    "Skipped {} duplicates for {} hash: {} ({})",
+                    dup_fileset.extras.len(),
+                    dup_fileset.algorithm,
+                    dup_fileset.hash,
+                    reason
+                );
+                continue;
+            }
+            println!(
+                "For {} hash: {}, {}",
+                dup_fileset.algorithm, dup_fileset.hash, reason
+            );
+        }
+        bytes_deduplicated += dup_fileset.bytes_deduplicated;
         if args.shared.verbose {
             println!(
-                "Found {} duplicates for hash: {}",
+                "Found {} duplicates for {} hash: {}{}",
                 dup_fileset.extras.len(),
-                dup_fileset.hash
+                dup_fileset.algorithm,
+                dup_fileset.hash,
+                if dup_fileset.normalized_match {
+                    " (normalized match)"
+                } else {
+                    ""
+                }
             );
         }
         for file in &dup_fileset.extras {
@@ -593,14 +2127,65 @@ fn start_search<T: FileOperations>(file_ops: &T, args: &Args) -> Result<SearchRe
                     file.modified_at.to_rfc2822(),
                     bytesize::ByteSize(file.size)
                 );
+                for link in &file.hardlinks {
+                    println!("  already linked: {}", link);
+                }
             }
-            duplicates_total_size += file.size as i64;
+            duplicates_total_size += reclaimable_size(file) as i64;
             if args.shared.verbose {
                 println!();
             }
         }
     }
 
+    // list the files the action failed on, rather than leaving them in the progress output
+    let failures: Vec<FileOutcome> = dup_fileset_vec
+        .iter()
+        .flat_map(|dup_fileset| &dup_fileset.outcomes)
+        .filter(|outcome| outcome.failed())
+        .cloned()
+        .collect();
+    if !failures.is_empty() {
+        println!(
+            "Failed to {} {} files:",
+            command_name(&args.command),
+            failures.len()
+        );
+        for failure in &failures {
+            println!(
+                "  {}: {}",
+                failure.path,
+                failure.error.as_deref().unwrap_or_default()
+            );
+        }
+    }
+
+    // write the plan if configured, so it can be reviewed and applied later
+    if let Some(plan_out) = &args.shared.plan_out {
+        let plan = create_plan(args, &dup_fileset_vec);
+        plan.save(Path::new(plan_out))?;
+        println!(
+            "Plan of {} sets written to {} (use the apply command to carry it out)",
+            plan.sets.len(),
+            plan_out
+        );
+        if !plan.sets.is_empty() && !plan.has_actions() {
+            println!(
+                "The plan has no actions, as {} does not change files. Edit the action of its sets before applying it",
+                command_name(&args.command)
+            );
+        }
+    }
+
+    // record the results in the database if configured
+    if let Some(db) = &args.shared.db {
+        let report = create_structured_report(args, &dup_fileset_vec);
+        match Database::open(Path::new(db)).and_then(|mut database| database.record_run(&report)) {
+            Ok(run_id) => println!("Results recorded in database {} as run {}", db, run_id),
+            Err(e) => eprintln!("*** Failed to record results in database {}: {}", db, e),
+        }
+    }
+
     // create report if configured
     if args.shared.create_report {
         let _ = create_duplicate_report(args, dup_fileset_vec);
@@ -610,6 +2195,8 @@ fn start_search<T: FileOperations>(file_ops: &T, args: &Args) -> Result<SearchRe
     let search_results: SearchResults = SearchResults {
         number_duplicates: duplicates_found,
         total_size: duplicates_total_size as usize,
+        bytes_deduplicated,
+        failures,
     };
     Ok(search_results)
 }
@@ -637,10 +2224,7 @@ fn get_files_in_directory(
         Ok(metadata) => {
             if !metadata.is_dir() {
                 eprintln!("The path provided {} is not a directory", folder_path);
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "The path provided is not a directory",
-                ));
+                return Err(io::Error::other("The path provided is not a directory"));
             }
         }
         Err(e) => {
@@ -817,8 +2401,8 @@ fn get_files_in_directory(
 
             if path.is_file() {
                 // determine if the file matches the wildcard
-                let wildcard_pattern = glob::Pattern::new(&args.shared.wildcard)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                let wildcard_pattern =
+                    glob::Pattern::new(&args.shared.wildcard).map_err(io::Error::other)?;
                 if !wildcard_pattern.matches_path(path) {
                     if args.shared.verbose {
                         let _ = multi.println(format!(
@@ -833,7 +2417,7 @@ fn get_files_in_directory(
                 if !args.shared.exclusion_wildcard.is_empty() {
                     let exclusion_wildcard_pattern =
                         glob::Pattern::new(&args.shared.exclusion_wildcard)
-                            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                            .map_err(|e| io::Error::other(e.to_string()))?;
                     if exclusion_wildcard_pattern.matches_path(path) {
                         if args.shared.verbose {
                             let _ = multi.println(format!(
@@ -894,12 +2478,29 @@ fn get_files_in_directory(
                     + chrono::Duration::from_std(modified_at.duration_since(UNIX_EPOCH).unwrap())
                         .unwrap();
 
+                // get the device and inode, so hardlinks to the same file can be identified
+                let (device, inode, links): (u64, u64, u64);
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::MetadataExt;
+                    (device, inode, links) = (meta.dev(), meta.ino(), meta.nlink());
+                }
+                #[cfg(not(unix))]
+                {
+                    (device, inode, links) = (0, 0, 1);
+                }
+
                 // store results in our files vector
                 let file_info = FileInfo {
                     path: path.to_str().unwrap().to_string(),
                     size,
                     created_at: created_at_utc_datetime,
                     modified_at: modified_at_utc_datetime,
+                    line_endings_normalized: false,
+                    device,
+                    inode,
+                    links,
+                    hardlinks: vec![],
                 };
                 files.push(file_info);
 
@@ -925,102 +2526,402 @@ fn get_files_in_directory(
     Ok(files)
 }
 
+/// # collapse_hardlinks
+/// Collapse files that are hardlinks to the same device and inode into one logical file.
+/// The file with the first path (alphabetically) is kept, with the other paths recorded as its hardlinks.
+/// * `files` - The files to collapse.
+/// # Returns
+/// * `(Vec<FileInfo>, usize)` - The logical files, and the number of paths collapsed into another file.
+fn collapse_hardlinks(files: Vec<FileInfo>) -> (Vec<FileInfo>, usize) {
+    let files_count = files.len();
+    let mut logical_files: Vec<FileInfo> = Vec::new();
+    let mut inode_map: HashMap<(u64, u64), Vec<FileInfo>> = HashMap::new();
+    for file in files {
+        // an inode of 0 means the platform does not provide one
+        if file.inode == 0 {
+            logical_files.push(file);
+        } else {
+            inode_map
+                .entry((file.device, file.inode))
+                .or_default()
+                .push(file);
+        }
+    }
+    for (_, mut links) in inode_map {
+        links.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut file = links.remove(0);
+        file.hardlinks = links.into_iter().map(|link| link.path).collect();
+        logical_files.push(file);
+    }
+    let collapsed = files_count - logical_files.len();
+    (logical_files, collapsed)
+}
+
+/// # reclaimable_size
+/// The space freed by removing a file and the hardlinks to it found in the search.
+/// If the file has other hardlinks outside the search, no space is freed.
+/// * `file` - The file.
+/// * `u64` - The number of bytes that can be reclaimed.
+fn reclaimable_size(file: &FileInfo) -> u64 {
+    if file.links > 1 + file.hardlinks.len() as u64 {
+        0
+    } else {
+        file.size
+    }
+}
+
+/// # group_files_by_size
+/// Group files by their size. A file with a unique size cannot have a duplicate,
+/// so any group containing a single file is dropped.
+/// * `files` - The files to group.
+/// # Returns
+/// * `(Vec<FileInfo>, usize)` - The remaining candidate files, and the number of files removed.
+fn group_files_by_size(files: Vec<FileInfo>) -> (Vec<FileInfo>, usize) {
+    let files_count = files.len();
+    let mut size_map: HashMap<u64, Vec<FileInfo>> = HashMap::new();
+    for file in files {
+        size_map.entry(file.size).or_default().push(file);
+    }
+
+    // remove all entries from size_map where the files.len() <= 1
+    size_map.retain(|_, files| files.len() > 1);
+
+    let candidates: Vec<FileInfo> = size_map.into_values().flatten().collect();
+    let removed = files_count - candidates.len();
+    (candidates, removed)
+}
+
 /// # identify_duplicates
-/// Identify duplicate files based on their MD5 hash
+/// Identify duplicate files based on the hash of their contents.
+/// Duplicates are identified in stages, each stage removing files that can not have a duplicate:
+/// 1. files are grouped by size
+/// 2. files sharing a size have the head (and optionally the tail) of their contents hashed
+/// 3. files whose partial hashes collide have their full contents hashed,
+///    unless the head and tail already covered the whole file
+///
+/// Files with a cached hash are not read again, and go straight to the full hash stage.
+/// When line endings are normalized, files that differ only in line endings have different sizes,
+/// so the size and partial hash stages are skipped.
 /// * `args` - The command line arguments.
 /// * `files` - The files to process.
 /// * `running` - The running flag.
 fn identify_duplicates(args: &Args, files: Vec<FileInfo>) -> HashMap<String, Vec<FileInfo>> {
-    let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
     let multi = MultiProgress::new();
-    let workers = get_number_of_threads(args);
 
     let bar2 = if args.shared.quiet {
         multi.add(ProgressBar::hidden())
     } else {
-        multi.add(ProgressBar::new_spinner().with_message("Identifying duplicates..."))
+        multi.add(ProgressBar::new_spinner().with_message("Grouping files by size..."))
     };
 
     bar2.enable_steady_tick(Duration::from_millis(100));
 
-    let bar = if args.shared.quiet {
-        multi.add(ProgressBar::hidden())
+    // stage 1 - group by size, files with a unique size can not have a duplicate
+    let stage_start = Instant::now();
+    let total_files = files.len();
+    let normalize_line_endings = args.shared.normalize_line_endings;
+    let algorithm = args.shared.hash_algorithm;
+    let (files, removed_by_size) = if normalize_line_endings {
+        (files, 0)
     } else {
-        multi.add(ProgressBar::new(files.len().try_into().unwrap()))
+        group_files_by_size(files)
     };
-
-    bar.set_style(
-        ProgressStyle::with_template("{bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
-            .unwrap()
-            .progress_chars("##-"),
+    print_stage_statistics(
+        args,
+        &multi,
+        "Size grouping",
+        total_files,
+        files.len(),
+        stage_start.elapsed(),
     );
 
-    // we will use a thread pool to optimize the hashing process
-    // the thread pool will use one thread per cpu core
-
-    let pool = ThreadPool::new(workers);
-    let (tx, rx) = channel();
-    let files_count = files.len();
-
-    // setup our jobs for the thread pool
-    for file in files {
-        let tx = tx.clone();
-        let bar = bar.clone();
-        let file_path = file.path.clone();
-
-        let bar_clone = bar.clone();
-        pool.execute(move || {
-            let hash_result = get_hash_of_file(&file_path, &bar_clone);
-            // handle an error
-            match hash_result {
-                Ok(hash_string) => tx.send((hash_string, file.clone())).unwrap(),
-                Err(e) => {
-                    eprintln!("{}", e);
-                    tx.send((String::new(), file.clone())).unwrap()
+    // look up the cached hash of any file that has not changed since it was last hashed,
+    // so only new or changed files are read
+    let mut cache = open_hash_cache(args);
+    let cache_algorithm = if normalize_line_endings {
+        format!("{}+crlf", algorithm)
+    } else {
+        algorithm.to_string()
+    };
+    let lookup_count = files.len();
+    let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+    let mut cache_keys: HashMap<String, CacheKey> = HashMap::new();
+    let mut uncached_files: Vec<FileInfo> = Vec::new();
+    for mut file in files {
+        if let Some(ref cache) = cache {
+            if let Ok(key) = CacheKey::for_file(&file.path, &cache_algorithm) {
+                if let Some(entry) = cache.get(&key) {
+                    file.line_endings_normalized = entry.normalized;
+                    hash_map.entry(entry.hash.clone()).or_default().push(file);
+                    continue;
                 }
+                cache_keys.insert(file.path.clone(), key);
             }
-        });
+        }
+        uncached_files.push(file);
     }
-
-    // wait for the jobs to complete, and process the results
-    rx.iter().take(files_count).for_each(|(hash_string, file)| {
-        if hash_string.is_empty() {
-            if args.shared.debug {
-                let _ = multi.println(format!(
-                    "File: {} [{} bytes] [error calculating hash]",
-                    file.path, file.size
-                ));
+    let cache_hits = lookup_count - uncached_files.len();
+
+    // a file sharing a size with a cached file has no partial hash to compare with,
+    // so it goes straight to the full hash
+    let cached_sizes: HashSet<u64> = hash_map.values().flatten().map(|file| file.size).collect();
+    let (mut files, mut unfiltered_files): (Vec<FileInfo>, Vec<FileInfo>) = uncached_files
+        .into_iter()
+        .partition(|file| !cached_sizes.contains(&file.size));
+
+    // stage 2 - hash the head/tail of the remaining candidates
+    let mut partial_full_hashes: HashMap<String, Vec<FileInfo>> = HashMap::new();
+    let head_size = args.shared.partial_hash_head_kib * 1024;
+    let tail_size = args.shared.partial_hash_tail_kib * 1024;
+    if !normalize_line_endings && (head_size > 0 || tail_size > 0) {
+        bar2.set_message(format!(
+            "Comparing partial hashes... ({} files with a unique size skipped)",
+            removed_by_size
+        ));
+        let stage_start = Instant::now();
+        let candidates_count = files.len();
+        let mut partial_hash_map = hash_files(args, &multi, files, move |file: &mut FileInfo| {
+            // include the size in the key so files of different sizes are never grouped
+            get_partial_hash_of_file(&file.path, algorithm, file.size, head_size, tail_size)
+                .map(|hash| format!("{}-{}", file.size, hash))
+        });
+        partial_hash_map.retain(|_, files| files.len() > 1);
+        files = Vec::new();
+        for (partial_hash, group) in partial_hash_map {
+            for file in group {
+                if file.size <= head_size.saturating_add(tail_size) {
+                    // the head and tail cover the whole file, so the partial hash is its full hash
+                    let hash = partial_hash.split_once('-').map_or("", |(_, hash)| hash);
+                    partial_full_hashes
+                        .entry(hash.to_string())
+                        .or_default()
+                        .push(file);
+                } else {
+                    files.push(file);
+                }
             }
-            return;
-        }
-        if args.shared.verbose {
-            let _ = multi.println(format!(
-                "File: {} [{} bytes] [hash: {}]",
-                file.path, file.size, hash_string
-            ));
         }
-        // add the file and hash to the map
-        // if the hash doesn't exist, create a new vector
-        if !hash_map.contains_key(&hash_string) {
-            let vec = vec![file];
-            hash_map.insert(hash_string.to_string(), vec);
+        print_stage_statistics(
+            args,
+            &multi,
+            "Partial hash",
+            candidates_count,
+            files.len()
+                + partial_full_hashes
+                    .values()
+                    .map(|files| files.len())
+                    .sum::<usize>(),
+            stage_start.elapsed(),
+        );
+    }
+    files.append(&mut unfiltered_files);
+
+    // stage 3 - hash the full contents of the remaining candidates
+    bar2.set_message(format!(
+        "Identifying duplicates... ({} files with a unique size skipped)",
+        removed_by_size
+    ));
+    let stage_start = Instant::now();
+    let candidates_count = cache_hits
+        + partial_full_hashes
+            .values()
+            .map(|files| files.len())
+            .sum::<usize>()
+        + files.len();
+
+    let mut hashed_map = hash_files(args, &multi, files, move |file: &mut FileInfo| {
+        if normalize_line_endings {
+            let (hash, normalized) = get_normalized_hash_of_file(&file.path, algorithm)?;
+            file.line_endings_normalized = normalized;
+            Ok(hash)
         } else {
-            let vec = hash_map.get_mut(&hash_string).unwrap();
-            vec.push(file);
+            get_hash_of_file(&file.path, algorithm, &ProgressBar::hidden())
         }
-        bar.inc(1);
     });
 
-    bar.finish();
-    bar2.finish();
+    for (hash_string, files) in partial_full_hashes {
+        hashed_map.entry(hash_string).or_default().extend(files);
+    }
+
+    // merge the new hashes with the cached hashes, and update the cache
+    for (hash_string, files) in hashed_map {
+        for file in &files {
+            if let (Some(ref mut cache), Some(key)) = (&mut cache, cache_keys.remove(&file.path)) {
+                cache.insert(
+                    key,
+                    CacheEntry {
+                        path: file.path.clone(),
+                        hash: hash_string.clone(),
+                        normalized: file.line_endings_normalized,
+                    },
+                );
+            }
+        }
+        hash_map.entry(hash_string).or_default().extend(files);
+    }
+    if let Some(cache) = cache {
+        if args.shared.debug {
+            let _ = multi.println(format!(
+                "Hash cache: {} of {} files found in {}",
+                cache_hits,
+                lookup_count,
+                cache.path().display()
+            ));
+        }
+        if let Err(e) = cache.save() {
+            eprintln!(
+                "Unable to save hash cache {}: {}",
+                cache.path().display(),
+                e
+            );
+        }
+    }
+    let remaining = hash_map
+        .values()
+        .filter(|files| files.len() > 1)
+        .map(|files| files.len())
+        .sum::<usize>();
+    print_stage_statistics(
+        args,
+        &multi,
+        "Full hash",
+        candidates_count,
+        remaining,
+        stage_start.elapsed(),
+    );
+
+    if args.shared.verbose {
+        for (hash_string, files) in hash_map.iter() {
+            for file in files {
+                let _ = multi.println(format!(
+                    "File: {} [{} bytes] [hash: {}]",
+                    file.path, file.size, hash_string
+                ));
+            }
+        }
+    }
 
+    bar2.finish();
     multi.remove(&bar2);
-    multi.remove(&bar);
     multi.clear().unwrap();
 
     hash_map
 }
 
+/// # hash_files
+/// Hash files using a thread pool, and group them by the resulting hash
+/// * `args` - The command line arguments.
+/// * `multi` - The progress bar.
+/// * `files` - The files to hash.
+/// * `hash_fn` - The function used to hash a single file.  It may update the file information.
+/// # Returns
+/// A map of hash to the files with that hash.  Files that could not be hashed are left out.
+fn hash_files<F>(
+    args: &Args,
+    multi: &MultiProgress,
+    files: Vec<FileInfo>,
+    hash_fn: F,
+) -> HashMap<String, Vec<FileInfo>>
+where
+    F: Fn(&mut FileInfo) -> Result<String, std::io::Error> + Send + Sync + 'static,
+{
+    let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+    let workers = get_number_of_threads(args);
+
+    let bar = if args.shared.quiet {
+        multi.add(ProgressBar::hidden())
+    } else {
+        multi.add(ProgressBar::new(files.len().try_into().unwrap()))
+    };
+
+    bar.set_style(
+        ProgressStyle::with_template("{bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
+            .unwrap()
+            .progress_chars("##-"),
+    );
+
+    // we will use a thread pool to optimize the hashing process
+    // the thread pool will use one thread per cpu core
+
+    let pool = ThreadPool::new(workers);
+    let (tx, rx) = channel();
+    let files_count = files.len();
+    let hash_fn = std::sync::Arc::new(hash_fn);
+
+    // setup our jobs for the thread pool
+    for mut file in files {
+        let tx = tx.clone();
+        let hash_fn = hash_fn.clone();
+
+        pool.execute(move || {
+            let hash_result = hash_fn(&mut file);
+            // handle an error
+            match hash_result {
+                Ok(hash_string) => tx.send((hash_string, file)).unwrap(),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    tx.send((String::new(), file)).unwrap()
+                }
+            }
+        });
+    }
+
+    // wait for the jobs to complete, and process the results
+    rx.iter().take(files_count).for_each(|(hash_string, file)| {
+        bar.inc(1);
+        if hash_string.is_empty() {
+            if args.shared.debug {
+                let _ = multi.println(format!(
+                    "File: {} [{} bytes] [error calculating hash]",
+                    file.path, file.size
+                ));
+            }
+            return;
+        }
+        // add the file and hash to the map
+        // if the hash doesn't exist, create a new vector
+        hash_map.entry(hash_string).or_default().push(file);
+    });
+
+    bar.finish();
+    multi.remove(&bar);
+
+    hash_map
+}
+
+/// # print_stage_statistics
+/// Print how many files a stage of duplicate identification removed.
+/// Printed when verbose or debug is set, debug also includes the time taken by the stage.
+/// * `args` - The command line arguments.
+/// * `multi` - The progress bar.
+/// * `stage` - The name of the stage.
+/// * `input` - The number of files going into the stage.
+/// * `remaining` - The number of files remaining after the stage.
+/// * `elapsed` - The time taken by the stage.
+fn print_stage_statistics(
+    args: &Args,
+    multi: &MultiProgress,
+    stage: &str,
+    input: usize,
+    remaining: usize,
+    elapsed: Duration,
+) {
+    if !args.shared.verbose && !args.shared.debug {
+        return;
+    }
+    let mut line = format!(
+        "{}: {} of {} files removed, {} candidates remaining",
+        stage,
+        input - remaining,
+        input,
+        remaining
+    );
+    if args.shared.debug {
+        line.push_str(&format!(" [{}]", humantime::format_duration(elapsed)));
+    }
+    let _ = multi.println(line);
+}
+
 /// # process_duplicates
 /// Process the duplicate files using the method specified in cmd line args
 /// * `file_ops` - The file operations object.
@@ -1062,12 +2963,23 @@ fn process_duplicates<T: FileOperations>(
     let method = match &args.command {
         Commands::Move { method, .. } => method,
         Commands::Copy { method, .. } => method,
-        Commands::Delete { method } => method,
+        Commands::Delete { method, .. } => method,
         Commands::Find { method } => method,
+        Commands::Link { method } => method,
+        Commands::Symlink { method, .. } => method,
+        Commands::Reflink { method } => method,
+        Commands::Quarantine { method, .. } => method,
+        // nothing to process for commands that do not search for duplicates
+        Commands::Cache { .. }
+        | Commands::Restore { .. }
+        | Commands::Purge { .. }
+        | Commands::Undo { .. }
+        | Commands::Apply { .. } => return Vec::new(),
     };
+    let method = &method.with_path_rules(&args.shared.prefer, &args.shared.avoid);
 
     // if the duplicate selection method is "interactive" then we need to turn off the progress bars
-    if *method == DuplicateSelectionMethod::Interactive {
+    if method.is_interactive() {
         bar.finish();
         bar2.finish();
         multi.remove(&bar2);
@@ -1082,19 +2994,22 @@ fn process_duplicates<T: FileOperations>(
     // store the results
     let mut dup_results: Vec<DuplicateFileSet> = Vec::new();
 
+    let verify = should_verify(args);
+    let rules = KeeperRules::from_args(args);
+    let journal = open_journal(args);
+    // a plan records what would be done, without doing it
+    let planning = args.shared.plan_out.is_some();
+
     // get list of files to process
     for (index, (hash, files)) in hash_map.iter().enumerate() {
         new_hash_map.insert(hash.clone(), files.clone());
 
-        // if the command is FindDuplicates, then we don't need to process the duplicates
-        if let Commands::Find { .. } = args.command {
-            continue;
-        }
-
-        let dup_fileset = match select_duplicate_files(
+        let mut dup_fileset = match select_duplicate_files(
             args.command.clone(),
-            method.clone(),
+            method,
+            &rules,
             hash,
+            args.shared.hash_algorithm,
             files,
             index + 1,
             hash_map.len(),
@@ -1105,16 +3020,26 @@ fn process_duplicates<T: FileOperations>(
                 if e.kind() == InteractiveErrorKind::Skip {
                     DuplicateFileSet {
                         hash: hash.to_string(),
+                        algorithm: args.shared.hash_algorithm,
                         keeper: None,
                         extras: vec![],
                         result: DuplicateResult::Skipped,
+                        normalized_match: false,
+                        bytes_deduplicated: 0,
+                        outcomes: vec![],
+                        reason: None,
                     }
                 } else {
                     DuplicateFileSet {
                         hash: hash.to_string(),
+                        algorithm: args.shared.hash_algorithm,
                         keeper: None,
                         extras: vec![],
                         result: DuplicateResult::Aborted,
+                        normalized_match: false,
+                        bytes_deduplicated: 0,
+                        outcomes: vec![],
+                        reason: None,
                     }
                 }
             }
@@ -1123,15 +3048,86 @@ fn process_duplicates<T: FileOperations>(
             break;
         }
         // only process if there is a file to process
-        if dup_fileset.keeper.is_some() {
+        if let Some(keeper) = dup_fileset.keeper.clone() {
             if args.shared.debug {
-                if let Some(ref keeper) = dup_fileset.keeper {
-                    let _ = multi.println(format!("Selected File: {}", keeper.path));
+                let _ = multi.println(format!("Selected File: {}", keeper.path));
+            }
+
+            // compare each duplicate with the keeper before acting on it
+            // any that do not match are split off into their own set and left alone
+            if verify {
+                let (verified, mismatched) = verify_duplicates(
+                    &keeper,
+                    &dup_fileset.extras,
+                    args.shared.normalize_line_endings,
+                    &multi,
+                );
+                dup_fileset.extras = verified;
+                if !mismatched.is_empty() {
+                    dup_results.push(DuplicateFileSet {
+                        hash: hash.to_string(),
+                        algorithm: dup_fileset.algorithm,
+                        keeper: Some(keeper.clone()),
+                        extras: mismatched,
+                        result: DuplicateResult::Mismatched,
+                        normalized_match: dup_fileset.normalized_match,
+                        bytes_deduplicated: 0,
+                        outcomes: vec![],
+                        reason: None,
+                    });
                 }
+                if dup_fileset.extras.is_empty() {
+                    bar.inc(1);
+                    continue;
+                }
+            }
+
+            // a plan records what would be done without doing it, and find only reports the duplicates
+            if planning || matches!(args.command, Commands::Find { .. }) {
+                dup_results.push(dup_fileset);
+                bar.inc(1);
+                continue;
             }
 
             for file in &dup_fileset.extras {
-                let _ = process_a_duplicate_file(file_ops, args, file, hash, &mut multi);
+                let result = process_a_duplicate_file(
+                    file_ops,
+                    args,
+                    file,
+                    Some(&keeper),
+                    journal.as_ref(),
+                    hash,
+                    &mut multi,
+                );
+                if let Ok(processed) = &result {
+                    dup_fileset.bytes_deduplicated += processed.bytes_deduplicated;
+                }
+                dup_fileset
+                    .outcomes
+                    .push(FileOutcome::new(&file.path, result));
+                // hardlinks share the storage of the duplicate, so reflinking it covers them too
+                if let Commands::Reflink { .. } = args.command {
+                    yield_now();
+                    continue;
+                }
+                // hardlinks to the duplicate are processed too, otherwise no space is freed
+                for link in &file.hardlinks {
+                    let link_file = FileInfo {
+                        path: link.clone(),
+                        hardlinks: vec![],
+                        ..file.clone()
+                    };
+                    let result = process_a_duplicate_file(
+                        file_ops,
+                        args,
+                        &link_file,
+                        Some(&keeper),
+                        journal.as_ref(),
+                        hash,
+                        &mut multi,
+                    );
+                    dup_fileset.outcomes.push(FileOutcome::new(link, result));
+                }
                 yield_now();
             }
         }
@@ -1146,54 +3142,295 @@ fn process_duplicates<T: FileOperations>(
     multi.remove(&bar2);
     multi.remove(&bar);
     multi.clear().unwrap();
+    if let Some(journal) = journal.filter(|journal| journal.is_written()) {
+        println!(
+            "Changes recorded in journal: {} (use the undo command to reverse them)",
+            journal.path().display()
+        );
+    }
     dup_results
 }
 
+/// # should_verify
+/// Determine if duplicates should be compared byte-for-byte with the keeper before being processed.
+/// Verification is on by default for the delete, move, link, symlink and quarantine commands.
+/// * `args` - The command line arguments.
+/// * `bool` - True if duplicates should be verified.
+fn should_verify(args: &Args) -> bool {
+    if args.shared.no_verify {
+        return false;
+    }
+    match args.command {
+        Commands::Find { .. }
+        | Commands::Cache { .. }
+        | Commands::Restore { .. }
+        | Commands::Purge { .. }
+        | Commands::Undo { .. }
+        | Commands::Apply { .. } => false,
+        Commands::Delete { .. }
+        | Commands::Move { .. }
+        | Commands::Link { .. }
+        | Commands::Symlink { .. }
+        | Commands::Quarantine { .. } => true,
+        // the kernel compares the bytes itself before sharing them
+        Commands::Reflink { .. } => args.shared.verify,
+        Commands::Copy { .. } => args.shared.verify,
+    }
+}
+
+/// # verify_duplicates
+/// Compare each duplicate byte-for-byte with the keeper.
+/// * `keeper` - The file being kept.
+/// * `extras` - The duplicates of the keeper.
+/// * `normalize_line_endings` - Treat CRLF and LF line endings as equal in text files.
+/// * `multi` - The progress bar.
+/// # Returns
+/// * `(Vec<FileInfo>, Vec<FileInfo>)` - The duplicates that matched the keeper, and those that did not or could not be compared.
+fn verify_duplicates(
+    keeper: &FileInfo,
+    extras: &[FileInfo],
+    normalize_line_endings: bool,
+    multi: &MultiProgress,
+) -> (Vec<FileInfo>, Vec<FileInfo>) {
+    let mut verified: Vec<FileInfo> = Vec::new();
+    let mut mismatched: Vec<FileInfo> = Vec::new();
+    for file in extras {
+        match files_are_identical(&keeper.path, &file.path, normalize_line_endings) {
+            Ok(true) => verified.push(file.clone()),
+            Ok(false) => {
+                let _ = multi.println(format!(
+                    "*** Skipping {}: contents do not match {}",
+                    file.path, keeper.path
+                ));
+                mismatched.push(file.clone());
+            }
+            Err(e) => {
+                let _ = multi.println(format!(
+                    "*** Skipping {}: unable to compare with {}: {}",
+                    file.path, keeper.path, e
+                ));
+                mismatched.push(file.clone());
+            }
+        }
+    }
+    (verified, mismatched)
+}
+
+/// # files_are_identical
+/// Compare the contents of two files byte-for-byte
+/// * `path_a` - The path to the first file.
+/// * `path_b` - The path to the second file.
+/// * `normalize_line_endings` - Treat CRLF and LF line endings as equal in text files.
+/// * `Result<bool, std::io::Error>` - True if the contents of the files are identical.
+/// # Errors
+/// * `std::io::Error` - An error occurred reading either file.
+fn files_are_identical(
+    path_a: &str,
+    path_b: &str,
+    normalize_line_endings: bool,
+) -> Result<bool, std::io::Error> {
+    let file_a = std::fs::File::open(path_a)?;
+    let file_b = std::fs::File::open(path_b)?;
+    if normalize_line_endings && (is_text_file(path_a)? || is_text_file(path_b)?) {
+        return readers_are_identical(
+            LineEndingNormalizer::new(file_a),
+            LineEndingNormalizer::new(file_b),
+        );
+    }
+    if file_a.metadata()?.len() != file_b.metadata()?.len() {
+        return Ok(false);
+    }
+    readers_are_identical(file_a, file_b)
+}
+
+/// # readers_are_identical
+/// Compare everything read from two readers byte-for-byte
+/// * `reader_a` - The first reader.
+/// * `reader_b` - The second reader.
+/// * `Result<bool, std::io::Error>` - True if the readers produced identical bytes.
+fn readers_are_identical<A: Read, B: Read>(
+    mut reader_a: A,
+    mut reader_b: B,
+) -> Result<bool, std::io::Error> {
+    let mut buffer_a = vec![0; BUFFER_READ_SIZE];
+    let mut buffer_b = vec![0; BUFFER_READ_SIZE];
+    loop {
+        let bytes_read_a = read_full(&mut reader_a, &mut buffer_a)?;
+        let bytes_read_b = read_full(&mut reader_b, &mut buffer_b)?;
+        if bytes_read_a != bytes_read_b || buffer_a[..bytes_read_a] != buffer_b[..bytes_read_b] {
+            return Ok(false);
+        }
+        if bytes_read_a == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// # read_full
+/// Read from a reader until the buffer is full or the end of the file is reached
+/// * `reader` - The reader to read from.
+/// * `buffer` - The buffer to fill.
+/// * `Result<usize, std::io::Error>` - The number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, std::io::Error> {
+    let mut total = 0;
+    while total < buffer.len() {
+        let bytes_read = reader.read(&mut buffer[total..])?;
+        if bytes_read == 0 {
+            break;
+        }
+        total += bytes_read;
+    }
+    Ok(total)
+}
+
+/// # quarantine_a_duplicate_file
+/// Move a duplicate file into quarantine and record it in the manifest.  If it can not be
+/// recorded, it is moved back, so the quarantine never holds a file it can not restore.
+/// * `file_ops` - The file operations object.
+/// * `file` - The file to quarantine.
+/// * `keeper` - The file being kept.
+/// * `hash` - The hash of the file.
+/// * `destination` - Where to put the file in quarantine.
+/// * `location` - The quarantine folder.
+/// * `Result<String, std::io::Error>` - Where the file is in quarantine.
+/// # Errors
+/// * `std::io::Error` - The file could not be moved or recorded.
+fn quarantine_a_duplicate_file<T: FileOperations>(
+    file_ops: &T,
+    file: &FileInfo,
+    keeper: Option<&FileInfo>,
+    hash: &str,
+    destination: &str,
+    location: &Path,
+) -> Result<String, std::io::Error> {
+    if let Some(parent) = Path::new(destination).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // the quarantine can be on another filesystem, where the file is copied instead
+    let mut copied = false;
+    let destination = match file_ops.rename(&file.path, destination, false) {
+        Err(e) if quarantine::is_cross_device(&e) => {
+            quarantine::move_file(Path::new(&file.path), Path::new(destination))?;
+            copied = true;
+            destination.to_string()
+        }
+        result => result?,
+    };
+    let entry = QuarantineEntry {
+        quarantined_path: destination.clone(),
+        original_path: file.path.clone(),
+        keeper_path: keeper.map(|keeper| keeper.path.clone()).unwrap_or_default(),
+        hash: hash.to_string(),
+        size: file.size,
+        created_at: file.created_at,
+        modified_at: file.modified_at,
+        quarantined_at: Utc::now(),
+    };
+    if let Err(e) = Manifest::append(location, &entry) {
+        if copied {
+            let _ = quarantine::move_file(Path::new(&destination), Path::new(&file.path));
+        } else {
+            let _ = file_ops.rename(&destination, &file.path, false);
+        }
+        return Err(e);
+    }
+    Ok(destination)
+}
+
 /// # process_a_duplicate_file
 /// Process a duplicate file based on the command line arguments
 /// * `file_ops` - The file operations object.
 /// * `args` - The command line arguments.
 /// * `file` - The file to process.
+/// * `keeper` - The file being kept, if one was selected.
+/// * `journal` - The journal to record the operation in, if there is one.
 /// * `hash` - The hash of the file.
 /// * `multi` - The progress bar.
-/// * `Result<(), std::io::Error>` - The result of the operation.
+/// * `Result<ProcessedFile, std::io::Error>` - Where the file went, and the number of bytes deduplicated by the filesystem.
 /// # Errors
 /// * `std::io::Error` - An error occurred during the operation.
 fn process_a_duplicate_file<T: FileOperations>(
     file_ops: &T,
     args: &Args,
     file: &FileInfo,
+    keeper: Option<&FileInfo>,
+    journal: Option<&Journal>,
     hash: &str,
     multi: &mut MultiProgress,
-) -> Result<(), std::io::Error> {
+) -> Result<ProcessedFile, std::io::Error> {
     let source = &file.path;
+    // protected files are never modified, even if they were selected as a duplicate
+    let modifies_file = !matches!(args.command, Commands::Find { .. } | Commands::Copy { .. });
+    if modifies_file && KeeperRules::from_args(args).protects(source) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is protected", source),
+        ));
+    }
     //let file_name = Path::new(&file.path).file_name().unwrap().to_str().unwrap();
     let location = match &args.command {
         Commands::Move { location, .. } => location,
         Commands::Copy { location, .. } => location,
-        Commands::Delete { method: _ } => "",
+        Commands::Quarantine { location, .. } => location,
+        Commands::Delete { .. } => "",
         Commands::Find { method: _ } => "",
+        Commands::Link { method: _ } => "",
+        Commands::Symlink { .. } => "",
+        Commands::Reflink { .. } => "",
+        Commands::Cache { .. }
+        | Commands::Restore { .. }
+        | Commands::Purge { .. }
+        | Commands::Undo { .. }
+        | Commands::Apply { .. } => "",
     };
 
     let flatten = match &args.command {
         Commands::Move { flatten, .. } => *flatten,
         Commands::Copy { flatten, .. } => *flatten,
-        Commands::Delete { method: _ } => false,
+        // the manifest records where quarantined files came from
+        Commands::Quarantine { .. } => true,
+        Commands::Delete { .. } => false,
         Commands::Find { method: _ } => false,
+        Commands::Link { method: _ } => false,
+        Commands::Symlink { .. } => false,
+        Commands::Reflink { .. } => false,
+        Commands::Cache { .. }
+        | Commands::Restore { .. }
+        | Commands::Purge { .. }
+        | Commands::Undo { .. }
+        | Commands::Apply { .. } => false,
     };
 
     let no_hash_folder = match &args.command {
         Commands::Move { no_hash_folder, .. } => *no_hash_folder,
         Commands::Copy { no_hash_folder, .. } => *no_hash_folder,
-        Commands::Delete { method: _ } => false,
+        Commands::Quarantine { .. } => false,
+        Commands::Delete { .. } => false,
         Commands::Find { method: _ } => false,
+        Commands::Link { method: _ } => false,
+        Commands::Symlink { .. } => false,
+        Commands::Reflink { .. } => false,
+        Commands::Cache { .. }
+        | Commands::Restore { .. }
+        | Commands::Purge { .. }
+        | Commands::Undo { .. }
+        | Commands::Apply { .. } => false,
     };
 
     let overwrite = match &args.command {
         Commands::Move { overwrite, .. } => *overwrite,
         Commands::Copy { overwrite, .. } => *overwrite,
-        Commands::Delete { method: _ } => false,
+        Commands::Quarantine { .. } => false,
+        Commands::Delete { .. } => false,
         Commands::Find { method: _ } => false,
+        Commands::Link { method: _ } => false,
+        Commands::Symlink { .. } => false,
+        Commands::Reflink { .. } => false,
+        Commands::Cache { .. }
+        | Commands::Restore { .. }
+        | Commands::Purge { .. }
+        | Commands::Undo { .. }
+        | Commands::Apply { .. } => false,
     };
 
     let relative_path = Path::new(&file.path)
@@ -1233,20 +3470,47 @@ fn process_a_duplicate_file<T: FileOperations>(
     destination.push('/');
     destination.push_str(Path::new(&file.path).file_name().unwrap().to_str().unwrap());
 
+    // when linking, the destination is the file being kept
+    let links_to_keeper = matches!(
+        args.command,
+        Commands::Link { .. } | Commands::Symlink { .. } | Commands::Reflink { .. }
+    );
+    if links_to_keeper {
+        destination = keeper.map(|keeper| keeper.path.clone()).unwrap_or_default();
+    }
+    // files are never overwritten in quarantine, so they can all be restored
+    if let Commands::Quarantine { .. } = args.command {
+        destination = quarantine::unused_path(Path::new(&destination))
+            .to_str()
+            .unwrap()
+            .to_string();
+    }
+
     let mut error: Option<std::io::Error> = None;
+    let mut journaled: Option<(JournalOperation, Option<String>)> = None;
+    let mut bytes_deduplicated: u64 = 0;
 
     let command_text: String = match args.command {
         Commands::Find { .. } => "Find".to_string(),
         Commands::Move { .. } => "Move".to_string(),
         Commands::Copy { .. } => "Copy".to_string(),
         Commands::Delete { .. } => "Delete".to_string(),
+        Commands::Link { .. } => "Link".to_string(),
+        Commands::Symlink { .. } => "Symlink".to_string(),
+        Commands::Reflink { .. } => "Reflink".to_string(),
+        Commands::Quarantine { .. } => "Quarantine".to_string(),
+        Commands::Cache { .. } => "Cache".to_string(),
+        Commands::Restore { .. } => "Restore".to_string(),
+        Commands::Purge { .. } => "Purge".to_string(),
+        Commands::Undo { .. } => "Undo".to_string(),
+        Commands::Apply { .. } => "Apply".to_string(),
     };
 
     // if not a dry run, then perform the operation
     if !args.shared.dry_run {
         if args.shared.verbose {
-            // location is empty for Find and Delete commands
-            if location.is_empty() {
+            // location is empty for Find and Delete, and the keeper is the destination when linking
+            if location.is_empty() && !links_to_keeper {
                 let _ = multi.println(format!("{}ing: {}", command_text, source));
             } else {
                 let _ = multi.println(format!(
@@ -1257,12 +3521,29 @@ fn process_a_duplicate_file<T: FileOperations>(
         }
 
         match args.command {
-            Commands::Find { .. } => {}
-            Commands::Move { .. } => {
-                if let Err(result) = file_ops.rename(source, &destination, overwrite) {
-                    error = Some(result);
+            Commands::Find { .. }
+            | Commands::Cache { .. }
+            | Commands::Restore { .. }
+            | Commands::Purge { .. }
+            | Commands::Undo { .. }
+            | Commands::Apply { .. } => {}
+            Commands::Quarantine { .. } => match quarantine_a_duplicate_file(
+                file_ops,
+                file,
+                keeper,
+                hash,
+                &destination,
+                Path::new(location),
+            ) {
+                Ok(quarantined) => {
+                    journaled = Some((JournalOperation::Quarantine, Some(quarantined)))
                 }
-            }
+                Err(result) => error = Some(result),
+            },
+            Commands::Move { .. } => match file_ops.rename(source, &destination, overwrite) {
+                Ok(moved) => journaled = Some((JournalOperation::Move, Some(moved))),
+                Err(result) => error = Some(result),
+            },
             Commands::Copy { .. } => {
                 if let Err(result) = fs::create_dir_all(&destination_folder) {
                     let _ = multi.println(
@@ -1273,15 +3554,52 @@ fn process_a_duplicate_file<T: FileOperations>(
                         .as_str(),
                     );
                 }
-                if let Err(result) = file_ops.copy(source, &destination, overwrite) {
-                    error = Some(result);
+                match file_ops.copy(source, &destination, overwrite) {
+                    Ok(copied) => journaled = Some((JournalOperation::Copy, Some(copied))),
+                    Err(result) => error = Some(result),
                 }
             }
-            Commands::Delete { .. } => {
-                if let Err(result) = file_ops.remove_file(source) {
-                    error = Some(result);
+            Commands::Delete { .. } => match file_ops.remove_file(source) {
+                // a file that was moved somewhere, such as the trash, can be put back
+                Ok(Some(trashed)) => journaled = Some((JournalOperation::Trash, Some(trashed))),
+                Ok(None) => journaled = Some((JournalOperation::Delete, None)),
+                Err(result) => error = Some(result),
+            },
+            Commands::Link { .. } => match keeper {
+                // a device of 0 means the platform does not provide one
+                Some(keeper)
+                    if keeper.device != 0 && file.device != 0 && keeper.device != file.device =>
+                {
+                    error = Some(io::Error::other(format!(
+                        "{} and {} are on different filesystems",
+                        source, keeper.path
+                    )));
                 }
-            }
+                Some(keeper) => match file_ops.hard_link(&keeper.path, source) {
+                    Ok(()) => journaled = Some((JournalOperation::Link, Some(keeper.path.clone()))),
+                    Err(result) => error = Some(result),
+                },
+                None => error = Some(io::Error::other("No file selected to link to")),
+            },
+            Commands::Symlink { relative, .. } => match keeper {
+                Some(keeper) => match file_ops.symlink(&keeper.path, source, relative) {
+                    Ok(()) => {
+                        journaled = Some((JournalOperation::Symlink, Some(keeper.path.clone())))
+                    }
+                    Err(result) => error = Some(result),
+                },
+                None => error = Some(io::Error::other("No file selected to link to")),
+            },
+            Commands::Reflink { .. } => match keeper {
+                Some(keeper) => match file_ops.reflink(&keeper.path, source) {
+                    Ok(bytes) => {
+                        bytes_deduplicated = bytes;
+                        journaled = Some((JournalOperation::Reflink, Some(keeper.path.clone())));
+                    }
+                    Err(result) => error = Some(result),
+                },
+                None => error = Some(io::Error::other("No file selected to link to")),
+            },
         }
 
         if error.is_some() {
@@ -1290,6 +3608,24 @@ fn process_a_duplicate_file<T: FileOperations>(
                 command_text, source, destination, error
             ));
         }
+
+        // record what was done, so it can be undone
+        if let (Some(journal), Some((operation, destination))) = (journal, &journaled) {
+            let entry = JournalEntry {
+                operation: *operation,
+                source: source.to_string(),
+                destination: destination.clone(),
+                hash: hash.to_string(),
+                timestamp: Utc::now(),
+            };
+            if let Err(e) = journal.record(&entry) {
+                let _ = multi.println(format!(
+                    "*** Failed to write to journal {}: {}",
+                    journal.path().display(),
+                    e
+                ));
+            }
+        }
     } else if args.shared.verbose {
         let _ = multi.println(format!(
             "Dry run: Would {} {} to {}",
@@ -1299,53 +3635,213 @@ fn process_a_duplicate_file<T: FileOperations>(
 
     match error {
         Some(e) => Err(e),
-        None => Ok(()),
+        None => Ok(ProcessedFile {
+            destination: journaled.and_then(|(_, destination)| destination),
+            bytes_deduplicated,
+        }),
     }
 }
 
 /// # get_hash_of_file
-/// Get the MD5 hash of a file
+/// Get the hash of the exact bytes of a file
 /// * `file_path` - The path to the file.
+/// * `algorithm` - The hash algorithm to use.
 /// * `bar` - The progress bar.
-/// * `Result<String, std::io::Error>` - The MD5 hash of the file.
+/// * `Result<String, std::io::Error>` - The hash of the file.
 /// # Errors
 /// * `std::io::Error` - An error occurred during the operation.
-fn get_hash_of_file(file_path: &str, _bar: &ProgressBar) -> Result<String, std::io::Error> {
+fn get_hash_of_file(
+    file_path: &str,
+    algorithm: HashAlgorithm,
+    _bar: &ProgressBar,
+) -> Result<String, std::io::Error> {
     let result = std::fs::File::open(file_path);
     match result {
-        Ok(mut f) => {
-            //let mut file = std::fs::File::open(file_path).unwrap();
-            let mut hasher = md5::Md5::new();
-            let mut buffer = [0; BUFFER_READ_SIZE]; // Read in chunks
+        Ok(mut f) => get_hash_of_reader(&mut f, algorithm),
+        Err(e) => {
+            eprintln!("{:?}", e);
+            Err(e)
+        }
+    }
+}
 
-            loop {
-                let bytes_read = f.read(&mut buffer)?;
-                if bytes_read == 0 {
-                    break;
+/// # get_normalized_hash_of_file
+/// Get the hash of a file, treating CRLF and LF line endings as equal if the file is text.
+/// Binary files are hashed using their exact bytes.
+/// * `file_path` - The path to the file.
+/// * `algorithm` - The hash algorithm to use.
+/// * `Result<(String, bool), std::io::Error>` - The hash of the file, and true if any line endings were normalized.
+/// # Errors
+/// * `std::io::Error` - An error occurred during the operation.
+fn get_normalized_hash_of_file(
+    file_path: &str,
+    algorithm: HashAlgorithm,
+) -> Result<(String, bool), std::io::Error> {
+    if !is_text_file(file_path)? {
+        let hash = get_hash_of_file(file_path, algorithm, &ProgressBar::hidden())?;
+        return Ok((hash, false));
+    }
+    let mut reader = LineEndingNormalizer::new(std::fs::File::open(file_path)?);
+    let hash = get_hash_of_reader(&mut reader, algorithm)?;
+    Ok((hash, reader.normalized))
+}
+
+/// # get_hash_of_reader
+/// Get the hash of everything read from a reader
+/// * `reader` - The reader to hash.
+/// * `algorithm` - The hash algorithm to use.
+/// * `Result<String, std::io::Error>` - The hash.
+fn get_hash_of_reader<R: Read>(
+    reader: &mut R,
+    algorithm: HashAlgorithm,
+) -> Result<String, std::io::Error> {
+    let mut hasher = algorithm.new_hasher();
+    let mut buffer = vec![0; BUFFER_READ_SIZE]; // Read in chunks
+
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+
+    Ok(hasher.finalize())
+}
+
+/// # is_text_file
+/// Detect if a file is text.  A file is considered text if its first 8000 bytes contain no NUL bytes.
+/// * `file_path` - The path to the file.
+/// * `Result<bool, std::io::Error>` - True if the file is text.
+fn is_text_file(file_path: &str) -> Result<bool, std::io::Error> {
+    let f = std::fs::File::open(file_path)?;
+    let mut buffer = Vec::new();
+    f.take(TEXT_DETECTION_SIZE).read_to_end(&mut buffer)?;
+    Ok(!buffer.contains(&0))
+}
+
+/// # LineEndingNormalizer
+/// Reader that converts CRLF line endings to LF as it reads.
+/// * `normalized` - True once any CRLF has been converted.
+struct LineEndingNormalizer<R: Read> {
+    inner: R,
+    buffer: Vec<u8>,
+    position: usize,
+    pending_cr: bool,
+    normalized: bool,
+}
+
+impl<R: Read> LineEndingNormalizer<R> {
+    fn new(inner: R) -> Self {
+        LineEndingNormalizer {
+            inner,
+            buffer: Vec::new(),
+            position: 0,
+            pending_cr: false,
+            normalized: false,
+        }
+    }
+
+    /// Read the next chunk from the inner reader into the buffer, normalizing line endings.
+    /// A trailing \r is held back until the next chunk shows if it is followed by \n.
+    fn fill_buffer(&mut self) -> Result<(), std::io::Error> {
+        let mut chunk = vec![0; BUFFER_READ_SIZE];
+        self.buffer.clear();
+        self.position = 0;
+        while self.buffer.is_empty() {
+            let bytes_read = self.inner.read(&mut chunk)?;
+            if bytes_read == 0 {
+                // end of the file, so a held back \r is not part of a line ending
+                if self.pending_cr {
+                    self.pending_cr = false;
+                    self.buffer.push(b'\r');
+                }
+                return Ok(());
+            }
+            for &b in &chunk[..bytes_read] {
+                if self.pending_cr {
+                    self.pending_cr = false;
+                    if b == b'\n' {
+                        self.normalized = true;
+                    } else {
+                        self.buffer.push(b'\r');
+                    }
+                }
+                if b == b'\r' {
+                    self.pending_cr = true;
+                } else {
+                    self.buffer.push(b);
                 }
-                // Normalize line endings by replacing \r\n with \n
-                let normalized_buffer: Vec<u8> = buffer[..bytes_read]
-                    .iter()
-                    .flat_map(|&b| if b == b'\r' { None } else { Some(b) })
-                    .collect();
-                hasher.update(&normalized_buffer);
             }
+        }
+        Ok(())
+    }
+}
 
-            let hash = hasher.finalize();
-            Ok(format!("{:x}", hash))
+impl<R: Read> Read for LineEndingNormalizer<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        if self.position >= self.buffer.len() {
+            self.fill_buffer()?;
         }
-        Err(e) => {
-            eprintln!("{:?}", e);
-            Err(e)
+        let available = &self.buffer[self.position..];
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// # get_partial_hash_of_file
+/// Get the hash of the head and tail of a file
+/// * `file_path` - The path to the file.
+/// * `algorithm` - The hash algorithm to use.
+/// * `size` - The size of the file in bytes.
+/// * `head_size` - The number of bytes to hash from the start of the file.
+/// * `tail_size` - The number of bytes to hash from the end of the file.
+/// * `Result<String, std::io::Error>` - The hash of the head and tail of the file.
+/// # Errors
+/// * `std::io::Error` - An error occurred during the operation.
+fn get_partial_hash_of_file(
+    file_path: &str,
+    algorithm: HashAlgorithm,
+    size: u64,
+    head_size: u64,
+    tail_size: u64,
+) -> Result<String, std::io::Error> {
+    let mut f = std::fs::File::open(file_path)?;
+    let mut hasher = algorithm.new_hasher();
+
+    // hash the head of the file, then the tail, skipping anything already covered by the head
+    let head_len = head_size.min(size);
+    let mut ranges = vec![(0, head_len)];
+    let tail_start = size.saturating_sub(tail_size).max(head_len);
+    if tail_size > 0 && tail_start < size {
+        ranges.push((tail_start, size - tail_start));
+    }
+
+    let mut buffer = vec![0; BUFFER_READ_SIZE]; // Read in chunks
+    for (start, len) in ranges {
+        f.seek(SeekFrom::Start(start))?;
+        let mut range = (&mut f).take(len);
+        loop {
+            let bytes_read = range.read(&mut buffer)?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..bytes_read]);
         }
     }
+
+    Ok(hasher.finalize())
 }
 
 /// # select_duplicate_files
 /// Select the duplicate files based on the method specified in the command line arguments
 /// * `command` - the command used (Find,Copy,Move,Delete)
-/// * `method` - The method to use.
+/// * `method` - The ranking of the files to keep.
+/// * `rules` - The path rules that override the file the ranking selects.
 /// * `hash` - The hash of the files.
+/// * `algorithm` - The algorithm used to calculate the hash.
 /// * `files` - The files to process.
 /// * `position_duplicates` - The index in the list of duplictes
 /// * `total_duplicates` - The total number of duplicates
@@ -1353,10 +3849,13 @@ fn get_hash_of_file(file_path: &str, _bar: &ProgressBar) -> Result<String, std::
 /// # Returns
 /// * `DuplicateFileSet` - The set of duplicate files.
 /// # `Error` - An Error or the user pressed ESC
+#[allow(clippy::too_many_arguments)]
 fn select_duplicate_files(
     command: Commands,
-    method: DuplicateSelectionMethod,
+    method: &KeeperRanking,
+    rules: &KeeperRules,
     hash: &String,
+    algorithm: HashAlgorithm,
     files: &[FileInfo],
     position_duplicates: usize,
     total_duplicates: usize,
@@ -1364,40 +3863,49 @@ fn select_duplicate_files(
 ) -> Result<DuplicateFileSet, InteractiveError> {
     let mut dup_fileset = DuplicateFileSet {
         hash: hash.to_string(),
+        algorithm,
         keeper: None,
         extras: vec![],
         result: DuplicateResult::Aborted,
+        normalized_match: files.iter().any(|file| file.line_endings_normalized),
+        bytes_deduplicated: 0,
+        outcomes: vec![],
+        reason: None,
     };
     if files.is_empty() {
         return Ok(dup_fileset);
     }
+    // no copy can be kept, so the set is left alone
+    if files.iter().all(|file| method.avoids(&file.path)) {
+        let mut sorted_files = files.to_owned();
+        sorted_files.sort_by(|a, b| a.path.cmp(&b.path));
+        dup_fileset.extras = sorted_files;
+        dup_fileset.result = DuplicateResult::Skipped;
+        dup_fileset.reason = Some(format!(
+            "all {} copies are avoided, so none can be kept",
+            files.len()
+        ));
+        return Ok(dup_fileset);
+    }
     match command {
-        Commands::Find { .. } => dup_fileset.result = DuplicateResult::Found,
+        Commands::Find { .. }
+        | Commands::Cache { .. }
+        | Commands::Restore { .. }
+        | Commands::Purge { .. }
+        | Commands::Undo { .. }
+        | Commands::Apply { .. } => dup_fileset.result = DuplicateResult::Found,
+        Commands::Quarantine { .. } => dup_fileset.result = DuplicateResult::Quarantined,
         Commands::Move { .. } => dup_fileset.result = DuplicateResult::Moved,
         Commands::Copy { .. } => dup_fileset.result = DuplicateResult::Copied,
         Commands::Delete { .. } => dup_fileset.result = DuplicateResult::Deleted,
+        Commands::Link { .. } => dup_fileset.result = DuplicateResult::Linked,
+        Commands::Symlink { .. } => dup_fileset.result = DuplicateResult::Symlinked,
+        Commands::Reflink { .. } => dup_fileset.result = DuplicateResult::Reflinked,
     }
-    match method {
-        DuplicateSelectionMethod::Newest => {
-            // keep the newest file, so return all other files
-            let mut sorted_files = files.to_owned();
-            sorted_files.sort_by(|a, b| b.modified_at.cmp(&a.modified_at));
-            let keeper = sorted_files.swap_remove(0);
-            dup_fileset.keeper = Some(keeper);
-            dup_fileset.extras = sorted_files;
-        }
-        DuplicateSelectionMethod::Oldest => {
-            // keep the oldest file, so return all other files
-            let mut sorted_files = files.to_owned();
-            sorted_files.sort_by(|a, b| a.modified_at.cmp(&b.modified_at));
-            let keeper = sorted_files.swap_remove(0);
-            dup_fileset.keeper = Some(keeper);
-            dup_fileset.extras = sorted_files;
-        }
+    match method.is_interactive() {
         // not sure how to test the interactive code right now
         #[cfg(not(tarpaulin_include))]
-        DuplicateSelectionMethod::Interactive => {
-            dup_fileset.extras = files.to_owned();
+        true => {
             let title = format!(
                 "Duplicate File Interactive Selector [{}/{}]",
                 position_duplicates, total_duplicates
@@ -1410,16 +3918,103 @@ fn select_duplicate_files(
             println!("Press S to skip to the next duplicate");
             println!("Press ESC to exit the program");
             println!();
-            println!("For hash [{}]:", hash);
+            if dup_fileset.normalized_match {
+                println!("For {} hash [{}] (normalized match):", algorithm, hash);
+            } else {
+                println!("For {} hash [{}]:", algorithm, hash);
+            }
             println!();
 
-            dup_fileset.keeper = get_interactive_selection(files)?
+            let keeper = get_interactive_selection(files)?;
+            dup_fileset.extras = files_other_than(files, keeper.as_ref());
+            dup_fileset.keeper = keeper;
+        }
+        _ => {
+            // keep the file the ranking prefers, so return all other files
+            let mut sorted_files = files.to_owned();
+            sorted_files.sort_by(|a, b| method.compare(a, b));
+            let keeper = sorted_files.remove(0);
+            dup_fileset.keeper = Some(keeper);
+            dup_fileset.extras = sorted_files;
         }
     }
+    keep_protected_files(&mut dup_fileset, rules);
 
     Ok(dup_fileset)
 }
 
+/// # keep_protected_files
+/// Make sure no protected file is among the duplicates, whichever file was selected.  A protected
+/// duplicate is kept instead of the file selected, and a set with several protected files is
+/// skipped, since only one file can be kept.  The decision is recorded as the reason of the set.
+/// * `dup_fileset` - The set of duplicate files, with the file to keep selected.
+/// * `rules` - The path rules.
+fn keep_protected_files(dup_fileset: &mut DuplicateFileSet, rules: &KeeperRules) {
+    let Some(keeper) = dup_fileset.keeper.take() else {
+        return;
+    };
+    // a file is protected if any of its hardlinks is, since they share the same contents
+    let mut protected: Vec<(String, String)> = std::iter::once(&keeper)
+        .chain(&dup_fileset.extras)
+        .filter_map(|file| {
+            rules
+                .protected_path(file)
+                .map(|protected_path| (file.path.clone(), protected_path.to_string()))
+        })
+        .collect();
+    if protected.len() > 1 {
+        protected.sort();
+        dup_fileset.extras.push(keeper);
+        dup_fileset.extras.sort_by(|a, b| a.path.cmp(&b.path));
+        dup_fileset.result = DuplicateResult::Skipped;
+        let protected_paths: Vec<String> = protected
+            .into_iter()
+            .map(|(_, protected_path)| protected_path)
+            .collect();
+        dup_fileset.reason = Some(format!(
+            "{} copies are protected ({}), so none can be modified",
+            protected_paths.len(),
+            protected_paths.join(", ")
+        ));
+        return;
+    }
+    match protected.first() {
+        Some((path, protected_path)) if *path != keeper.path => {
+            let index = dup_fileset
+                .extras
+                .iter()
+                .position(|file| file.path == *path)
+                .unwrap();
+            dup_fileset.reason = Some(if protected_path == path {
+                format!(
+                    "{} is protected, so it was kept instead of {}",
+                    path, keeper.path
+                )
+            } else {
+                format!(
+                    "{} has a protected hardlink {}, so it was kept instead of {}",
+                    path, protected_path, keeper.path
+                )
+            });
+            let protected_file = std::mem::replace(&mut dup_fileset.extras[index], keeper);
+            dup_fileset.keeper = Some(protected_file);
+        }
+        _ => dup_fileset.keeper = Some(keeper),
+    }
+}
+
+/// # files_other_than
+/// Get the files that are not the file to keep.
+/// * `files` - The set of duplicate files.
+/// * `keeper` - The file to keep, if one was selected.
+fn files_other_than(files: &[FileInfo], keeper: Option<&FileInfo>) -> Vec<FileInfo> {
+    files
+        .iter()
+        .filter(|file| keeper.is_none_or(|keeper| keeper.path != file.path))
+        .cloned()
+        .collect()
+}
+
 fn get_interactive_selection(files: &[FileInfo]) -> Result<Option<FileInfo>, InteractiveError> {
     // convert files into a string array
     let file_strings: Vec<String> = files
@@ -1449,53 +4044,126 @@ fn get_interactive_selection(files: &[FileInfo]) -> Result<Option<FileInfo>, Int
         .unwrap();
 
     // if selection.key is not none, then check to see what key the user pressed
-    if selection.key.is_some() {
-        let key = selection.key.unwrap();
+    if let Some(key) = selection.key {
         if key == Key::Char('s') {
             Err(InteractiveError::Skip())
         } else {
             Err(InteractiveError::Other(format!("{:?}", key)))
         }
-    } else if selection.index.is_none() {
+    } else if let Some(index) = selection.index {
+        Ok(Some(files[index].clone()))
+    } else {
         // user press escape
         Err(InteractiveError::Escape())
-    } else {
-        let index = selection.index.unwrap();
-        Ok(Some(files[index].clone()))
     }
 }
 
+/// # create_duplicate_report
+/// Write the report in the configured format.  The csv report has a row for the keeper and each
+/// duplicate of every set, with the action taken and where the file went or why it failed.
+/// * `args` - The command line arguments.
+/// * `dup_fileset_vec` - The duplicate sets.
+/// # Errors
+/// * `std::io::Error` - Reports are disabled, or the report could not be written.
 fn create_duplicate_report(
     args: &Args,
     dup_fileset_vec: Vec<DuplicateFileSet>,
 ) -> Result<(), std::io::Error> {
     if !args.shared.create_report {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "Report creation is disabled",
-        ));
+        return Err(io::Error::other("Report creation is disabled"));
+    }
+
+    match args.shared.report_format {
+        ReportFormat::Csv => {}
+        ReportFormat::Json => {
+            return create_structured_report(args, &dup_fileset_vec)
+                .write_json(Path::new(&args.shared.report_path))
+        }
+        ReportFormat::Ndjson => {
+            return create_structured_report(args, &dup_fileset_vec)
+                .write_ndjson(Path::new(&args.shared.report_path))
+        }
+        ReportFormat::Html => {
+            return create_structured_report(args, &dup_fileset_vec)
+                .write_html(Path::new(&args.shared.report_path))
+        }
     }
 
     let mut wtr = csv::Writer::from_path(&args.shared.report_path)?;
 
     wtr.write_record([
+        "Schema Version",
+        "Set Id",
+        "Role",
+        "Action",
+        "Algorithm",
         "Hash",
         "File Path",
         "Size",
         "Created At",
         "Modified At",
         "Result",
+        "Match",
+        "Destination",
+        "Error Kind",
+        "Error",
+        "Reason",
     ])?;
 
-    for dup_fileset in dup_fileset_vec.iter() {
-        for file in &dup_fileset.extras {
+    for (index, dup_fileset) in dup_fileset_vec.iter().enumerate() {
+        let files = dup_fileset
+            .keeper
+            .iter()
+            .map(|keeper| ("keeper", keeper))
+            .chain(dup_fileset.extras.iter().map(|extra| ("extra", extra)));
+        // each hardlink of a file has its own row, since it is processed separately
+        let paths = files.flat_map(|(role, file)| {
+            std::iter::once((role, role, file, &file.path)).chain(
+                file.hardlinks
+                    .iter()
+                    .map(move |link| ("hardlink", role, file, link)),
+            )
+        });
+        for (role, file_role, file, path) in paths {
+            let outcome = dup_fileset
+                .outcomes
+                .iter()
+                .find(|outcome| outcome.path == *path);
+            // only duplicates that were processed had something done to them
+            let action = match (file_role, outcome) {
+                ("keeper", _) => "keep",
+                (_, Some(_)) if args.shared.dry_run => "none (dry run)",
+                (_, Some(_)) => command_name(&args.command),
+                (_, None) => "none",
+            };
             wtr.write_record(&[
+                CSV_REPORT_SCHEMA_VERSION.to_string(),
+                (index + 1).to_string(),
+                role.to_string(),
+                action.to_string(),
+                dup_fileset.algorithm.to_string(),
                 dup_fileset.hash.clone(),
-                file.path.clone(),
+                path.clone(),
                 file.size.to_string(),
                 file.created_at.to_rfc3339(),
                 file.modified_at.to_rfc3339(),
                 format!("{:?}", dup_fileset.result),
+                if dup_fileset.normalized_match {
+                    "normalized match".to_string()
+                } else {
+                    "exact".to_string()
+                },
+                outcome
+                    .and_then(|outcome| outcome.destination.clone())
+                    .unwrap_or_default(),
+                outcome
+                    .and_then(|outcome| outcome.error_kind)
+                    .map(|kind| format!("{:?}", kind))
+                    .unwrap_or_default(),
+                outcome
+                    .and_then(|outcome| outcome.error.clone())
+                    .unwrap_or_default(),
+                dup_fileset.reason.clone().unwrap_or_default(),
             ])?;
         }
     }
@@ -1504,6 +4172,83 @@ fn create_duplicate_report(
     Ok(())
 }
 
+/// # create_structured_report
+/// Build the report written as json or ndjson, with the run metadata, the options used and every duplicate set.
+/// * `args` - The command line arguments.
+/// * `dup_fileset_vec` - The duplicate sets.
+/// * `Report` - The report.
+fn create_structured_report(args: &Args, dup_fileset_vec: &[DuplicateFileSet]) -> Report {
+    let report_file = |file: &FileInfo| ReportFile {
+        path: file.path.clone(),
+        size: file.size,
+        created_at: file.created_at,
+        modified_at: file.modified_at,
+        hardlinks: file.hardlinks.clone(),
+    };
+    Report {
+        metadata: RunMetadata {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            command: command_name(&args.command).to_string(),
+            created_at: Utc::now(),
+        },
+        options: ReportOptions {
+            path: args.shared.path.clone(),
+            wildcard: args.shared.wildcard.clone(),
+            exclusion_wildcard: args.shared.exclusion_wildcard.clone(),
+            recursive: args.shared.recursive,
+            include_empty_files: args.shared.include_empty_files,
+            include_hidden_files: args.shared.include_hidden_files,
+            dry_run: args.shared.dry_run,
+            hash_algorithm: args.shared.hash_algorithm,
+            normalize_line_endings: args.shared.normalize_line_endings,
+            verify: should_verify(args),
+        },
+        sets: dup_fileset_vec
+            .iter()
+            .map(|dup_fileset| ReportSet {
+                hash: dup_fileset.hash.clone(),
+                algorithm: dup_fileset.algorithm,
+                keeper: dup_fileset.keeper.as_ref().map(report_file),
+                extras: dup_fileset.extras.iter().map(report_file).collect(),
+                result: format!("{:?}", dup_fileset.result),
+                normalized_match: dup_fileset.normalized_match,
+                reason: dup_fileset.reason.clone(),
+                outcomes: dup_fileset
+                    .outcomes
+                    .iter()
+                    .map(|outcome| ReportOutcome {
+                        path: outcome.path.clone(),
+                        destination: outcome.destination.clone(),
+                        error_kind: outcome.error_kind.map(|kind| format!("{:?}", kind)),
+                        error: outcome.error.clone(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+/// # command_name
+/// The name of a command, as typed on the command line.
+fn command_name(command: &Commands) -> &'static str {
+    match command {
+        Commands::Find { .. } => "find",
+        Commands::Move { .. } => "move",
+        Commands::Copy { .. } => "copy",
+        Commands::Delete { .. } => "delete",
+        Commands::Link { .. } => "link",
+        Commands::Symlink { .. } => "symlink",
+        Commands::Reflink { .. } => "reflink",
+        Commands::Quarantine { .. } => "quarantine",
+        Commands::Restore { .. } => "restore",
+        Commands::Purge { .. } => "purge",
+        Commands::Undo { .. } => "undo",
+        Commands::Apply { .. } => "apply",
+        Commands::Cache { .. } => "cache",
+    }
+}
+
 /// # Tests
 ///
 /// Unit tests for the various functions and features of the program.
@@ -1522,27 +4267,47 @@ mod tests {
         fn copy(
             &self,
             _source: &str,
-            _destination: &str,
+            destination: &str,
             _overwrite: bool,
-        ) -> Result<(), std::io::Error> {
+        ) -> Result<String, std::io::Error> {
             // Mock implementation
-            Ok(())
+            Ok(destination.to_string())
         }
 
-        fn remove_file(&self, _source: &str) -> Result<(), std::io::Error> {
+        fn remove_file(&self, _source: &str) -> Result<Option<String>, std::io::Error> {
             // Mock implementation
-            Ok(())
+            Ok(None)
         }
 
         fn rename(
             &self,
             _source: &str,
-            _destination: &str,
+            destination: &str,
             _overwrite: bool,
+        ) -> Result<String, std::io::Error> {
+            // Mock implementation
+            Ok(destination.to_string())
+        }
+
+        fn hard_link(&self, _source: &str, _destination: &str) -> Result<(), std::io::Error> {
+            // Mock implementation
+            Ok(())
+        }
+
+        fn symlink(
+            &self,
+            _source: &str,
+            _destination: &str,
+            _relative: bool,
         ) -> Result<(), std::io::Error> {
             // Mock implementation
             Ok(())
         }
+
+        fn reflink(&self, _source: &str, _destination: &str) -> Result<u64, std::io::Error> {
+            // Mock implementation
+            Ok(4096)
+        }
     }
 
     struct MockFileOperationsError;
@@ -1553,14 +4318,14 @@ mod tests {
             _source: &str,
             _destination: &str,
             _overwrite: bool,
-        ) -> Result<(), std::io::Error> {
+        ) -> Result<String, std::io::Error> {
             // Mock implementation - produce an error
-            Err(io::Error::new(io::ErrorKind::Other, "Mock error"))
+            Err(io::Error::other("Mock error"))
         }
 
-        fn remove_file(&self, _source: &str) -> Result<(), std::io::Error> {
+        fn remove_file(&self, _source: &str) -> Result<Option<String>, std::io::Error> {
             // Mock implementation - produce an error
-            Err(io::Error::new(io::ErrorKind::Other, "Mock error"))
+            Err(io::Error::other("Mock error"))
         }
 
         fn rename(
@@ -1568,10 +4333,142 @@ mod tests {
             _source: &str,
             _destination: &str,
             _overwrite: bool,
+        ) -> Result<String, std::io::Error> {
+            // Mock implementation - produce an error
+            Err(io::Error::other("Mock error"))
+        }
+
+        fn hard_link(&self, _source: &str, _destination: &str) -> Result<(), std::io::Error> {
+            // Mock implementation - produce an error
+            Err(io::Error::other("Mock error"))
+        }
+
+        fn symlink(
+            &self,
+            _source: &str,
+            _destination: &str,
+            _relative: bool,
         ) -> Result<(), std::io::Error> {
             // Mock implementation - produce an error
-            Err(io::Error::new(io::ErrorKind::Other, "Mock error"))
+            Err(io::Error::other("Mock error"))
+        }
+
+        fn reflink(&self, _source: &str, _destination: &str) -> Result<u64, std::io::Error> {
+            // Mock implementation - produce an error
+            Err(io::Error::other("Mock error"))
+        }
+    }
+
+    /// Records the files removed or renamed, so tests can check which files were modified.
+    #[derive(Default)]
+    struct MockFileOperationsRecorder {
+        modified: std::cell::RefCell<Vec<String>>,
+    }
+
+    impl FileOperations for MockFileOperationsRecorder {
+        fn copy(
+            &self,
+            _source: &str,
+            destination: &str,
+            _overwrite: bool,
+        ) -> Result<String, std::io::Error> {
+            Ok(destination.to_string())
+        }
+
+        fn remove_file(&self, source: &str) -> Result<Option<String>, std::io::Error> {
+            self.modified.borrow_mut().push(source.to_string());
+            Ok(None)
+        }
+
+        fn rename(
+            &self,
+            source: &str,
+            destination: &str,
+            _overwrite: bool,
+        ) -> Result<String, std::io::Error> {
+            self.modified.borrow_mut().push(source.to_string());
+            Ok(destination.to_string())
+        }
+
+        fn hard_link(&self, _source: &str, _destination: &str) -> Result<(), std::io::Error> {
+            Ok(())
+        }
+
+        fn symlink(
+            &self,
+            _source: &str,
+            _destination: &str,
+            _relative: bool,
+        ) -> Result<(), std::io::Error> {
+            Ok(())
+        }
+
+        fn reflink(&self, _source: &str, _destination: &str) -> Result<u64, std::io::Error> {
+            Ok(0)
+        }
+    }
+
+    /// Fails to rename files as if they were on another filesystem, and otherwise succeeds.
+    #[cfg(unix)]
+    struct MockFileOperationsCrossDevice;
+
+    #[cfg(unix)]
+    impl FileOperations for MockFileOperationsCrossDevice {
+        fn copy(
+            &self,
+            _source: &str,
+            destination: &str,
+            _overwrite: bool,
+        ) -> Result<String, std::io::Error> {
+            Ok(destination.to_string())
+        }
+
+        fn remove_file(&self, _source: &str) -> Result<Option<String>, std::io::Error> {
+            Ok(None)
         }
+
+        fn rename(
+            &self,
+            _source: &str,
+            _destination: &str,
+            _overwrite: bool,
+        ) -> Result<String, std::io::Error> {
+            Err(io::Error::from_raw_os_error(libc::EXDEV))
+        }
+
+        fn hard_link(&self, _source: &str, _destination: &str) -> Result<(), std::io::Error> {
+            Ok(())
+        }
+
+        fn symlink(
+            &self,
+            _source: &str,
+            _destination: &str,
+            _relative: bool,
+        ) -> Result<(), std::io::Error> {
+            Ok(())
+        }
+
+        fn reflink(&self, _source: &str, _destination: &str) -> Result<u64, std::io::Error> {
+            Ok(0)
+        }
+    }
+
+    /// Create three files with the same contents in a new temporary folder, and the arguments to
+    /// delete the duplicates among them.  The folder is removed when the `TempDir` is dropped.
+    fn create_duplicate_files() -> (tempfile::TempDir, Args) {
+        let temp_dir = tempdir().unwrap();
+        for index in 0..3 {
+            std::fs::write(temp_dir.path().join(format!("file{}.txt", index)), "dupe").unwrap();
+        }
+        let mut args = create_default_command_line_arguments();
+        args.shared.path = temp_dir.path().to_str().unwrap().to_string();
+        args.shared.wildcard = "*.txt".to_string();
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+        (temp_dir, args)
     }
 
     fn create_default_command_line_arguments() -> Args {
@@ -1589,12 +4486,31 @@ mod tests {
             max_threads: Some(0),
             create_report: false,
             report_path: "./dupefinder-report.csv".to_string(),
+            report_format: ReportFormat::Csv,
+            partial_hash_head_kib: 64,
+            partial_hash_tail_kib: 0,
+            verify: false,
+            no_verify: false,
+            normalize_line_endings: false,
+            hash_algorithm: HashAlgorithm::Md5,
+            no_cache: true,
+            cache_path: None,
+            list_hardlinks: false,
+            journal: None,
+            no_journal: true,
+            plan_out: None,
+            db: None,
+            prefer: vec![],
+            avoid: vec![],
+            protect: vec![],
+            config: None,
+            no_config: false,
         };
         let s1 = shared_options.clone();
         Args {
             shared: s1,
             command: Commands::Find {
-                method: DuplicateSelectionMethod::Newest,
+                method: DuplicateSelectionMethod::Newest.into(),
             },
         }
     }
@@ -1740,6 +4656,7 @@ mod tests {
         let args = create_default_command_line_arguments();
         let hash = get_hash_of_file(
             &format!("{}//testdupe1.txt", args.shared.path.clone()),
+            HashAlgorithm::Md5,
             &ProgressBar::new_spinner().with_message("none"),
         );
         assert!(hash.is_ok());
@@ -1747,11 +4664,196 @@ mod tests {
         //assert_eq!(hash.unwrap(), "710c2d261165da2eac0e2321ea9ddbed");
     }
 
+    #[test]
+    fn test_get_hash_of_file_algorithms() {
+        let args = create_default_command_line_arguments();
+        let file_path = format!("{}//testdupe1.txt", args.shared.path.clone());
+        let bar = ProgressBar::new_spinner().with_message("none");
+        let hash = get_hash_of_file(&file_path, HashAlgorithm::Sha256, &bar);
+        assert_eq!(
+            hash.unwrap(),
+            "8c97ac252796c6775e038d19509f6443c1eff67cd0c8ac8ccfaf3167b062bcb3"
+        );
+        // each algorithm produces a different hash
+        let hashes: std::collections::HashSet<String> = [
+            HashAlgorithm::Md5,
+            HashAlgorithm::Xxh3,
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Sha256,
+        ]
+        .iter()
+        .map(|algorithm| get_hash_of_file(&file_path, *algorithm, &bar).unwrap())
+        .collect();
+        assert_eq!(hashes.len(), 4);
+    }
+
+    #[test]
+    fn test_identify_duplicates_blake3() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.hash_algorithm = HashAlgorithm::Blake3;
+        let multi = MultiProgress::new();
+        let files = get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
+        let hash_map = identify_duplicates(&args, files);
+        let duplicates_found = hash_map.values().filter(|files| files.len() > 1).count();
+        assert_eq!(duplicates_found, 2);
+        // blake3 hashes are 64 hex characters
+        assert!(hash_map.keys().all(|hash| hash.len() == 64));
+    }
+
+    #[test]
+    fn test_get_hash_of_file_line_endings() {
+        let temp_dir = tempdir().unwrap();
+        let unix_file = temp_dir.path().join("unix.txt");
+        let windows_file = temp_dir.path().join("windows.txt");
+        std::fs::write(&unix_file, b"line 1\nline 2\n").unwrap();
+        std::fs::write(&windows_file, b"line 1\r\nline 2\r\n").unwrap();
+        let unix_file = unix_file.to_str().unwrap();
+        let windows_file = windows_file.to_str().unwrap();
+        let bar = ProgressBar::new_spinner().with_message("none");
+
+        // exact bytes are hashed by default
+        assert_ne!(
+            get_hash_of_file(unix_file, HashAlgorithm::Md5, &bar).unwrap(),
+            get_hash_of_file(windows_file, HashAlgorithm::Md5, &bar).unwrap()
+        );
+
+        // normalized hashes match, and report the normalization
+        let (unix_hash, unix_normalized) =
+            get_normalized_hash_of_file(unix_file, HashAlgorithm::Md5).unwrap();
+        let (windows_hash, windows_normalized) =
+            get_normalized_hash_of_file(windows_file, HashAlgorithm::Md5).unwrap();
+        assert_eq!(unix_hash, windows_hash);
+        assert!(!unix_normalized);
+        assert!(windows_normalized);
+        assert!(files_are_identical(unix_file, windows_file, true).unwrap());
+        assert!(!files_are_identical(unix_file, windows_file, false).unwrap());
+    }
+
+    #[test]
+    fn test_get_normalized_hash_of_file_binary() {
+        let temp_dir = tempdir().unwrap();
+        let file1 = temp_dir.path().join("file1.bin");
+        let file2 = temp_dir.path().join("file2.bin");
+        std::fs::write(&file1, b"\0binary\r\n").unwrap();
+        std::fs::write(&file2, b"\0binary\n").unwrap();
+        let file1 = file1.to_str().unwrap();
+        let file2 = file2.to_str().unwrap();
+
+        // binary files are never normalized
+        let (hash1, normalized1) = get_normalized_hash_of_file(file1, HashAlgorithm::Md5).unwrap();
+        let (hash2, _) = get_normalized_hash_of_file(file2, HashAlgorithm::Md5).unwrap();
+        assert_ne!(hash1, hash2);
+        assert!(!normalized1);
+        assert!(!files_are_identical(file1, file2, true).unwrap());
+    }
+
+    #[test]
+    fn test_line_ending_normalizer() {
+        // a lone \r is kept, and a \r\n split across reads is still normalized
+        let input: &[u8] = b"a\rb\r\nc\r";
+        let mut reader = LineEndingNormalizer::new(input.chain(&b"\nd\r"[..]));
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"a\rb\nc\nd\r");
+        assert!(reader.normalized);
+    }
+
+    #[test]
+    fn test_identify_duplicates_normalize_line_endings() {
+        let temp_dir = tempdir().unwrap();
+        let mut args = create_default_command_line_arguments();
+        args.shared.path = temp_dir.path().to_str().unwrap().to_string();
+        std::fs::write(temp_dir.path().join("unix.txt"), b"line 1\nline 2\n").unwrap();
+        std::fs::write(temp_dir.path().join("windows.txt"), b"line 1\r\nline 2\r\n").unwrap();
+        let multi = MultiProgress::new();
+
+        // without normalization the files differ
+        let files = get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
+        let hash_map = identify_duplicates(&args, files);
+        assert_eq!(hash_map.values().filter(|files| files.len() > 1).count(), 0);
+
+        // with normalization they are a normalized match
+        args.shared.normalize_line_endings = true;
+        let files = get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
+        let hash_map = identify_duplicates(&args, files);
+        let duplicates: Vec<&Vec<FileInfo>> =
+            hash_map.values().filter(|files| files.len() > 1).collect();
+        assert_eq!(duplicates.len(), 1);
+        let bar = ProgressBar::new_spinner().with_message("none");
+        let dup_fileset = select_duplicate_files(
+            args.command.clone(),
+            &DuplicateSelectionMethod::Newest.into(),
+            &KeeperRules::default(),
+            &"testhash".to_owned(),
+            HashAlgorithm::Md5,
+            duplicates[0],
+            1,
+            1,
+            &bar,
+        )
+        .unwrap();
+        assert!(dup_fileset.normalized_match);
+    }
+
+    #[test]
+    fn test_get_partial_hash_of_file() {
+        let temp_dir = tempdir().unwrap();
+        let file1 = temp_dir.path().join("file1.bin");
+        let file2 = temp_dir.path().join("file2.bin");
+        std::fs::write(&file1, b"headXmiddleXtail").unwrap();
+        std::fs::write(&file2, b"headYmiddleYtail").unwrap();
+        let file1 = file1.to_str().unwrap();
+        let file2 = file2.to_str().unwrap();
+
+        // the files only differ in the middle, so matching head and tail hashes collide
+        assert_eq!(
+            get_partial_hash_of_file(file1, HashAlgorithm::Md5, 16, 4, 4).unwrap(),
+            get_partial_hash_of_file(file2, HashAlgorithm::Md5, 16, 4, 4).unwrap()
+        );
+        // a larger head reaches the differing byte
+        assert_ne!(
+            get_partial_hash_of_file(file1, HashAlgorithm::Md5, 16, 5, 0).unwrap(),
+            get_partial_hash_of_file(file2, HashAlgorithm::Md5, 16, 5, 0).unwrap()
+        );
+        // a head larger than the file hashes the full contents
+        assert_eq!(
+            get_partial_hash_of_file(file1, HashAlgorithm::Md5, 16, 1024, 1024).unwrap(),
+            get_partial_hash_of_file(file1, HashAlgorithm::Md5, 16, 16, 0).unwrap()
+        );
+
+        // ranges larger than the read buffer are hashed in chunks, and a head and tail that meet
+        // hash the same contents as the full hash
+        let large = temp_dir.path().join("large.bin");
+        let contents: Vec<u8> = (0..3 * BUFFER_READ_SIZE).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&large, &contents).unwrap();
+        let large = large.to_str().unwrap();
+        let size = contents.len() as u64;
+        assert_eq!(
+            get_partial_hash_of_file(large, HashAlgorithm::Md5, size, size / 2, size / 2 + 1)
+                .unwrap(),
+            get_hash_of_file(large, HashAlgorithm::Md5, &ProgressBar::hidden()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_partial_hash_of_file_bad_path() {
+        let args = create_default_command_line_arguments();
+        let hash = get_partial_hash_of_file(
+            &format!("{}//testdupe1-notfound.txt", args.shared.path.clone()),
+            HashAlgorithm::Md5,
+            20,
+            64,
+            64,
+        );
+        assert!(hash.is_err());
+    }
+
     #[test]
     fn test_get_hash_of_file_bad_path() {
         let args = create_default_command_line_arguments();
         let hash = get_hash_of_file(
             &format!("{}//testdupe1-notfound.txt", args.shared.path.clone()),
+            HashAlgorithm::Md5,
             &ProgressBar::new_spinner().with_message("none"),
         );
         assert!(hash.is_err());
@@ -1782,12 +4884,77 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_start_search_find() {
+        // find used to skip every set, reporting no duplicates
+        let temp_dir = tempdir().unwrap();
+        let report_path = temp_dir.path().join("report.csv");
+        let mut args = create_default_command_line_arguments();
+        args.shared.create_report = true;
+        args.shared.report_path = report_path.to_str().unwrap().to_string();
+        args.command = Commands::Find {
+            method: DuplicateSelectionMethod::Oldest.into(),
+        };
+
+        let result = start_search(&RealFileOperations, &args).unwrap();
+        // testdupe1-4.txt and testdupe1set2-3set2.txt
+        assert_eq!(result.number_duplicates, 2);
+        assert_eq!(result.total_size, 3 * 20 + 2 * 26);
+        assert_eq!(result.exit_code(), ExitCode::DuplicatesFound);
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .from_path(&report_path)
+            .unwrap();
+        let headers = rdr.headers().unwrap().clone();
+        let column = |name: &str| headers.iter().position(|header| header == name).unwrap();
+        let rows: Vec<csv::StringRecord> = rdr.records().map(|row| row.unwrap()).collect();
+        assert_eq!(rows.len(), 7);
+        assert!(rows.iter().all(|row| &row[column("Result")] == "Found"));
+        assert_eq!(
+            rows.iter()
+                .filter(|row| &row[column("Role")] == "keeper")
+                .count(),
+            2
+        );
+        // nothing is done to the duplicates
+        assert!(rows
+            .iter()
+            .filter(|row| &row[column("Role")] == "extra")
+            .all(|row| &row[column("Action")] == "none"));
+    }
+
+    #[test]
+    fn test_process_duplicates_find() {
+        let mut args = create_default_command_line_arguments();
+        args.command = Commands::Find {
+            method: DuplicateSelectionMethod::Oldest.into(),
+        };
+        let files: Vec<FileInfo> = (0..3)
+            .map(|index| FileInfo {
+                path: format!("file{}.txt", index),
+                modified_at: Utc::now() - chrono::Duration::days(index),
+                ..Default::default()
+            })
+            .collect();
+        let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        hash_map.insert("testhashkey".to_owned(), files);
+
+        // the keeper is chosen by the method, and the duplicates are left alone
+        let results = process_duplicates(&MockFileOperationsError, &args, &hash_map);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].result, DuplicateResult::Found);
+        assert_eq!(results[0].keeper.as_ref().unwrap().path, "file2.txt");
+        assert_eq!(results[0].extras.len(), 2);
+        assert!(results[0].outcomes.is_empty());
+    }
+
     #[test]
     fn test_start_search_copy() {
         let mut args = create_default_command_line_arguments();
         args.command = Commands::Copy {
             location: "/tmp".to_string(),
-            method: DuplicateSelectionMethod::Newest,
+            method: DuplicateSelectionMethod::Newest.into(),
             flatten: false,
             no_hash_folder: false,
             overwrite: true,
@@ -1803,7 +4970,7 @@ mod tests {
         let mut args = create_default_command_line_arguments();
         args.command = Commands::Move {
             location: "/tmp".to_string(),
-            method: DuplicateSelectionMethod::Newest,
+            method: DuplicateSelectionMethod::Newest.into(),
             flatten: false,
             no_hash_folder: false,
             overwrite: true,
@@ -1818,7 +4985,8 @@ mod tests {
     fn test_start_search_delete() {
         let mut args = create_default_command_line_arguments();
         args.command = Commands::Delete {
-            method: DuplicateSelectionMethod::Newest,
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
         };
         let file_ops = MockFileOperationsOk;
 
@@ -1863,7 +5031,7 @@ mod tests {
 
         args.command = Commands::Copy {
             location: temp_path,
-            method: DuplicateSelectionMethod::Newest,
+            method: DuplicateSelectionMethod::Newest.into(),
             flatten: false,
             no_hash_folder: false,
             overwrite: false,
@@ -1895,7 +5063,7 @@ mod tests {
 
         args.command = Commands::Copy {
             location: temp_path,
-            method: DuplicateSelectionMethod::Newest,
+            method: DuplicateSelectionMethod::Newest.into(),
             flatten: false,
             no_hash_folder: false,
             overwrite: false,
@@ -1927,7 +5095,7 @@ mod tests {
 
         args.command = Commands::Copy {
             location: temp_path,
-            method: DuplicateSelectionMethod::Newest,
+            method: DuplicateSelectionMethod::Newest.into(),
             flatten: false,
             no_hash_folder: false,
             overwrite: false,
@@ -1950,13 +5118,242 @@ mod tests {
     }
 
     #[test]
-    fn test_identify_duplicates() {
-        let args = create_default_command_line_arguments();
-        let multi = MultiProgress::new();
-        let files = get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
-        let hash_map = identify_duplicates(&args, files);
-        // duplicates are entries in hash_map with more than 1 file
-        let mut duplicates_found = 0;
+    fn test_create_report_csv_schema() {
+        let (temp_dir, mut args) = create_duplicate_files();
+        let search_dir = temp_dir.path();
+        std::fs::write(search_dir.join("other0.txt"), "other").unwrap();
+        std::fs::write(search_dir.join("other1.txt"), "other").unwrap();
+        let moved_dir = temp_dir.path().join("moved");
+        std::fs::create_dir(&moved_dir).unwrap();
+        let report_path = temp_dir.path().join("report.csv");
+        args.shared.dry_run = false;
+        args.shared.create_report = true;
+        args.shared.report_path = report_path.to_str().unwrap().to_string();
+        args.command = Commands::Move {
+            location: moved_dir.to_str().unwrap().to_string(),
+            method: DuplicateSelectionMethod::Newest.into(),
+            flatten: true,
+            no_hash_folder: true,
+            overwrite: false,
+        };
+        start_search(&RealFileOperations, &args).unwrap();
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .from_path(&report_path)
+            .unwrap();
+        let headers = rdr.headers().unwrap().clone();
+        assert_eq!(&headers[0], "Schema Version");
+        let column = |name: &str| headers.iter().position(|header| header == name).unwrap();
+        let rows: Vec<csv::StringRecord> = rdr.records().map(|row| row.unwrap()).collect();
+        // a keeper and its duplicates for each of the two sets
+        assert_eq!(rows.len(), 5);
+        assert!(rows.iter().all(|row| &row[0] == "4"));
+        for set_id in ["1", "2"] {
+            let set_rows: Vec<&csv::StringRecord> = rows
+                .iter()
+                .filter(|row| &row[column("Set Id")] == set_id)
+                .collect();
+            let keepers = set_rows
+                .iter()
+                .filter(|row| &row[column("Role")] == "keeper")
+                .count();
+            assert_eq!(keepers, 1);
+        }
+        for row in rows.iter().filter(|row| &row[column("Role")] == "extra") {
+            assert_eq!(&row[column("Action")], "move");
+            let destination = &row[column("Destination")];
+            assert!(destination.starts_with(moved_dir.to_str().unwrap()));
+            assert!(Path::new(destination).exists());
+            assert_eq!(&row[column("Error")], "");
+        }
+
+        // failures are recorded against the file
+        std::fs::write(search_dir.join("file3.txt"), "dupe").unwrap();
+        std::fs::write(search_dir.join("file4.txt"), "dupe").unwrap();
+        args.shared.no_verify = true;
+        start_search(&MockFileOperationsError, &args).unwrap();
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .from_path(&report_path)
+            .unwrap();
+        let rows: Vec<csv::StringRecord> = rdr.records().map(|row| row.unwrap()).collect();
+        let extra = rows
+            .iter()
+            .find(|row| &row[column("Role")] == "extra")
+            .unwrap();
+        assert_eq!(&extra[column("Destination")], "");
+        assert_ne!(&extra[column("Error")], "");
+    }
+
+    #[test]
+    fn test_create_report_csv_hardlinks() {
+        let temp_dir = tempdir().unwrap();
+        let report_path = temp_dir.path().join("report.csv");
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.shared.create_report = true;
+        args.shared.report_path = report_path.to_str().unwrap().to_string();
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+        let dup_fileset = DuplicateFileSet {
+            hash: "testhashkey".to_string(),
+            algorithm: HashAlgorithm::Md5,
+            keeper: Some(FileInfo {
+                path: "/data/a.txt".to_string(),
+                hardlinks: vec!["/data/a-link.txt".to_string()],
+                ..Default::default()
+            }),
+            extras: vec![FileInfo {
+                path: "/data/b.txt".to_string(),
+                hardlinks: vec!["/data/b-link.txt".to_string()],
+                ..Default::default()
+            }],
+            result: DuplicateResult::Deleted,
+            normalized_match: false,
+            bytes_deduplicated: 0,
+            outcomes: vec![
+                FileOutcome::new(
+                    "/data/b.txt",
+                    Ok(ProcessedFile {
+                        destination: None,
+                        bytes_deduplicated: 0,
+                    }),
+                ),
+                FileOutcome::new(
+                    "/data/b-link.txt",
+                    Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied")),
+                ),
+            ],
+            reason: None,
+        };
+        create_duplicate_report(&args, vec![dup_fileset]).unwrap();
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .from_path(&report_path)
+            .unwrap();
+        let rows: Vec<Vec<String>> = rdr
+            .records()
+            .map(|row| row.unwrap().iter().map(|field| field.to_string()).collect())
+            .collect();
+        // role, action, path and error of each row
+        let summary: Vec<[&str; 4]> = rows
+            .iter()
+            .map(|row| [&*row[2], &*row[3], &*row[6], &*row[14]])
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ["keeper", "keep", "/data/a.txt", ""],
+                ["hardlink", "keep", "/data/a-link.txt", ""],
+                ["extra", "delete", "/data/b.txt", ""],
+                ["hardlink", "delete", "/data/b-link.txt", "denied"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_create_report_json() {
+        let (temp_dir, mut args) = create_duplicate_files();
+        let report_path = temp_dir.path().join("report.json");
+        args.shared.create_report = true;
+        args.shared.report_path = report_path.to_str().unwrap().to_string();
+        args.shared.report_format = ReportFormat::Json;
+
+        assert!(start_search(&RealFileOperations, &args).is_ok());
+        // test to see if report file is a valid report, and has the keeper
+        let report: Report =
+            serde_json::from_reader(std::fs::File::open(&report_path).unwrap()).unwrap();
+        assert_eq!(report.metadata.tool, "dupefindr");
+        assert_eq!(report.metadata.command, "delete");
+        assert_eq!(report.options.path, args.shared.path);
+        assert_eq!(report.options.hash_algorithm, HashAlgorithm::Md5);
+        assert!(report.options.dry_run);
+        assert_eq!(report.sets.len(), 1);
+        assert_eq!(report.sets[0].result, "Deleted");
+        assert_eq!(report.sets[0].extras.len(), 2);
+        let keeper = report.sets[0].keeper.as_ref().unwrap();
+        assert!(keeper.path.starts_with(&args.shared.path));
+        assert_eq!(keeper.size, 4);
+
+        // writing the report that was read gives back the same document
+        let rewritten_path = temp_dir.path().join("rewritten.json");
+        report.write_json(&rewritten_path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&rewritten_path).unwrap(),
+            std::fs::read_to_string(&report_path).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_create_report_ndjson() {
+        let temp_dir = tempdir().unwrap();
+        for (index, contents) in ["one", "one", "two", "two", "two"].iter().enumerate() {
+            std::fs::write(temp_dir.path().join(format!("file{}.txt", index)), contents).unwrap();
+        }
+        let report_path = temp_dir.path().join("report.ndjson");
+        let mut args = create_default_command_line_arguments();
+        args.shared.path = temp_dir.path().to_str().unwrap().to_string();
+        args.shared.wildcard = "*.txt".to_string();
+        args.shared.create_report = true;
+        args.shared.report_path = report_path.to_str().unwrap().to_string();
+        args.shared.report_format = ReportFormat::Ndjson;
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+
+        assert!(start_search(&RealFileOperations, &args).is_ok());
+        // test to see if each line is a duplicate set
+        let contents = std::fs::read_to_string(&report_path).unwrap();
+        let mut sets: Vec<ReportSet> = Vec::new();
+        for line in contents.lines() {
+            let set: ReportSet = serde_json::from_str(line).unwrap();
+            // writing the set that was read gives back the same line
+            assert_eq!(serde_json::to_string(&set).unwrap(), line);
+            sets.push(set);
+        }
+        sets.sort_by_key(|set| set.extras.len());
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].extras.len(), 1);
+        assert_eq!(sets[1].extras.len(), 2);
+        assert!(sets.iter().all(|set| set.keeper.is_some()));
+    }
+
+    #[test]
+    fn test_start_search_db() {
+        let (temp_dir, mut args) = create_duplicate_files();
+        let db_path = temp_dir.path().join("results.sqlite");
+        args.shared.db = Some(db_path.to_str().unwrap().to_string());
+
+        start_search(&RealFileOperations, &args).unwrap();
+        start_search(&RealFileOperations, &args).unwrap();
+        let connection = rusqlite::Connection::open(&db_path).unwrap();
+        let runs: i64 = connection
+            .query_row("SELECT COUNT(*) FROM runs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(runs, 2);
+        let duplicates: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM duplicates WHERE run_id = 2",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(duplicates, 2);
+    }
+
+    #[test]
+    fn test_identify_duplicates() {
+        let args = create_default_command_line_arguments();
+        let multi = MultiProgress::new();
+        let files = get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
+        let hash_map = identify_duplicates(&args, files);
+        // duplicates are entries in hash_map with more than 1 file
+        let mut duplicates_found = 0;
         for (_hash, files) in hash_map.iter() {
             if files.len() > 1 {
                 duplicates_found += 1;
@@ -1982,6 +5379,129 @@ mod tests {
         assert_eq!(duplicates_found, 2);
     }
 
+    #[test]
+    fn test_identify_duplicates_partial_hash_disabled() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.partial_hash_head_kib = 0;
+        args.shared.partial_hash_tail_kib = 0;
+        let multi = MultiProgress::new();
+        let files = get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
+        let hash_map = identify_duplicates(&args, files);
+        let duplicates_found = hash_map.values().filter(|files| files.len() > 1).count();
+        assert_eq!(duplicates_found, 2);
+    }
+
+    #[test]
+    fn test_identify_duplicates_partial_hash_head_and_tail() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.debug = true;
+        args.shared.partial_hash_head_kib = 1;
+        args.shared.partial_hash_tail_kib = 1;
+        let multi = MultiProgress::new();
+        let files = get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
+        let hash_map = identify_duplicates(&args, files);
+        let duplicates_found = hash_map.values().filter(|files| files.len() > 1).count();
+        assert_eq!(duplicates_found, 2);
+    }
+
+    #[test]
+    fn test_identify_duplicates_partial_hash_covers_file() {
+        let temp_dir = tempdir().unwrap();
+        let mut args = create_default_command_line_arguments();
+        args.shared.path = temp_dir.path().to_str().unwrap().to_string();
+        for name in ["a.txt", "b.txt"] {
+            fs::write(temp_dir.path().join(name), "same contents").unwrap();
+        }
+        let multi = MultiProgress::new();
+        let files = get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
+        let hash_map = identify_duplicates(&args, files);
+        // the head covers the whole file, so its hash is the full hash
+        let hash = get_hash_of_file(
+            temp_dir.path().join("a.txt").to_str().unwrap(),
+            HashAlgorithm::Md5,
+            &ProgressBar::hidden(),
+        )
+        .unwrap();
+        assert_eq!(hash_map.len(), 1);
+        assert_eq!(hash_map[&hash].len(), 2);
+    }
+
+    #[test]
+    fn test_get_command_line_args_partial_hash_too_large() {
+        let too_large = (MAX_PARTIAL_HASH_KIB + 1).to_string();
+        for option in ["--partial-hash-head-kib", "--partial-hash-tail-kib"] {
+            assert!(Args::try_parse_from(["dupefindr", option, &too_large, "find"]).is_err());
+        }
+        let args = Args::try_parse_from([
+            "dupefindr",
+            "--partial-hash-head-kib",
+            &MAX_PARTIAL_HASH_KIB.to_string(),
+            "find",
+        ])
+        .unwrap();
+        assert_eq!(args.shared.partial_hash_head_kib, MAX_PARTIAL_HASH_KIB);
+    }
+
+    #[test]
+    fn test_identify_duplicates_with_cache() {
+        let temp_dir = tempdir().unwrap();
+        let cache_path = temp_dir.path().join("hashes.csv");
+        let mut args = create_default_command_line_arguments();
+        args.shared.no_cache = false;
+        args.shared.cache_path = Some(cache_path.to_str().unwrap().to_string());
+        let multi = MultiProgress::new();
+
+        // the first run fills the cache, the second uses it
+        for _ in 0..2 {
+            let files =
+                get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
+            let hash_map = identify_duplicates(&args, files);
+            let duplicates_found = hash_map.values().filter(|files| files.len() > 1).count();
+            assert_eq!(duplicates_found, 2);
+        }
+        let cache = HashCache::load(&cache_path).unwrap();
+        assert_eq!(cache.len(), 7);
+        assert_eq!(cache.count_by_algorithm(), vec![("md5".to_string(), 7)]);
+
+        // the cache commands work on the same cache
+        assert!(run_cache_command(&args, &CacheAction::Info).is_ok());
+        assert!(run_cache_command(&args, &CacheAction::Prune).is_ok());
+        assert_eq!(HashCache::load(&cache_path).unwrap().len(), 7);
+        assert!(run_cache_command(&args, &CacheAction::Invalidate { prefix: None }).is_ok());
+        assert_eq!(HashCache::load(&cache_path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_identify_duplicates_new_copy_of_cached_file() {
+        let temp_dir = tempdir().unwrap();
+        let data_dir = temp_dir.path().join("data");
+        fs::create_dir(&data_dir).unwrap();
+        fs::write(data_dir.join("a.txt"), "same contents").unwrap();
+        fs::write(data_dir.join("b.txt"), "same contents").unwrap();
+        let mut args = create_default_command_line_arguments();
+        args.shared.path = data_dir.to_str().unwrap().to_string();
+        args.shared.no_cache = false;
+        args.shared.cache_path = Some(
+            temp_dir
+                .path()
+                .join("hashes.csv")
+                .to_str()
+                .unwrap()
+                .to_string(),
+        );
+        let multi = MultiProgress::new();
+
+        let files = get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
+        identify_duplicates(&args, files);
+
+        // the new copy has no partial hash to compare with the cached files, but is still found
+        fs::write(data_dir.join("c.txt"), "same contents").unwrap();
+        let files = get_files_in_directory(&args, args.shared.path.clone(), &multi, true).unwrap();
+        let hash_map = identify_duplicates(&args, files);
+        assert_eq!(hash_map.len(), 1);
+        assert_eq!(hash_map.values().next().unwrap().len(), 3);
+    }
+
     #[test]
     fn test_identify_duplicates_no_files() {
         let args = create_default_command_line_arguments();
@@ -2008,6 +5528,7 @@ mod tests {
             size: 123,
             created_at: Utc::now(),
             modified_at: Utc::now(),
+            ..Default::default()
         };
         files.push(file);
         let hash_map = identify_duplicates(&args, files);
@@ -2021,6 +5542,88 @@ mod tests {
         assert_eq!(duplicates_found, 0);
     }
 
+    #[test]
+    fn test_group_files_by_size() {
+        let mut files = Vec::new();
+        for (name, size) in [("a.txt", 10), ("b.txt", 10), ("c.txt", 20), ("d.txt", 30)] {
+            files.push(FileInfo {
+                path: name.to_string(),
+                size,
+                created_at: Utc::now(),
+                modified_at: Utc::now(),
+                ..Default::default()
+            });
+        }
+        let (candidates, removed) = group_files_by_size(files);
+        // only the two files sharing a size remain
+        assert_eq!(removed, 2);
+        assert_eq!(candidates.len(), 2);
+        assert!(candidates.iter().all(|file| file.size == 10));
+    }
+
+    #[test]
+    fn test_collapse_hardlinks() {
+        let mut files = Vec::new();
+        for (name, inode) in [
+            ("b.txt", 1),
+            ("a.txt", 1),
+            ("c.txt", 2),
+            ("d.txt", 0),
+            ("e.txt", 0),
+        ] {
+            files.push(FileInfo {
+                path: name.to_string(),
+                size: 10,
+                device: 1,
+                inode,
+                links: 2,
+                ..Default::default()
+            });
+        }
+        let (files, collapsed) = collapse_hardlinks(files);
+        assert_eq!(collapsed, 1);
+        assert_eq!(files.len(), 4);
+        // the first path is kept, and the other is recorded as a hardlink
+        let file = files.iter().find(|file| file.inode == 1).unwrap();
+        assert_eq!(file.path, "a.txt");
+        assert_eq!(file.hardlinks, vec!["b.txt".to_string()]);
+        // all of the links were found, so the space can be reclaimed
+        assert_eq!(reclaimable_size(file), 10);
+        // a link outside the search means no space can be reclaimed
+        let file = files.iter().find(|file| file.inode == 2).unwrap();
+        assert_eq!(reclaimable_size(file), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_start_search_hardlinks() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("original.txt"), b"hardlink contents").unwrap();
+        std::fs::hard_link(
+            temp_dir.path().join("original.txt"),
+            temp_dir.path().join("link.txt"),
+        )
+        .unwrap();
+        let mut args = create_default_command_line_arguments();
+        args.shared.path = temp_dir.path().to_str().unwrap().to_string();
+        args.shared.list_hardlinks = true;
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+        let file_ops = MockFileOperationsOk;
+
+        // hardlinks alone are not duplicates
+        let result = start_search(&file_ops, &args).unwrap();
+        assert_eq!(result.number_duplicates, 0);
+
+        // a copy is a duplicate of the linked file, and only the space of one copy can be reclaimed
+        std::fs::write(temp_dir.path().join("copy.txt"), b"hardlink contents").unwrap();
+        let result = start_search(&file_ops, &args).unwrap();
+        assert_eq!(result.number_duplicates, 1);
+        assert_eq!(result.total_size, 17);
+    }
+
     #[test]
     fn test_select_duplicate_files_newest() {
         let args = create_default_command_line_arguments();
@@ -2030,24 +5633,29 @@ mod tests {
             size: 1024,
             created_at: Utc::now(),
             modified_at: Utc::now(),
+            ..Default::default()
         });
         files.push(FileInfo {
             path: format!("{}//testdupe2.txt", args.shared.path.clone()),
             size: 1024,
             created_at: Utc::now() - chrono::Duration::days(1),
             modified_at: Utc::now() - chrono::Duration::days(1),
+            ..Default::default()
         });
         files.push(FileInfo {
             path: format!("{}//testdupe3.txt", args.shared.path.clone()),
             size: 1024,
             created_at: Utc::now() - chrono::Duration::days(2),
             modified_at: Utc::now() - chrono::Duration::days(2),
+            ..Default::default()
         });
         let bar = ProgressBar::new_spinner().with_message("none");
         let dup_fileset = select_duplicate_files(
             args.command.clone(),
-            DuplicateSelectionMethod::Newest,
+            &DuplicateSelectionMethod::Newest.into(),
+            &KeeperRules::default(),
             &"testhash".to_owned(),
+            HashAlgorithm::Md5,
             &files,
             1,
             1,
@@ -2083,24 +5691,29 @@ mod tests {
             size: 1024,
             created_at: Utc::now(),
             modified_at: Utc::now(),
+            ..Default::default()
         });
         files.push(FileInfo {
             path: format!("{}//testdupe2.txt", args.shared.path.clone()),
             size: 1024,
             created_at: Utc::now() - chrono::Duration::days(1),
             modified_at: Utc::now() - chrono::Duration::days(1),
+            ..Default::default()
         });
         files.push(FileInfo {
             path: format!("{}//testdupe3.txt", args.shared.path.clone()),
             size: 1024,
             created_at: Utc::now() - chrono::Duration::days(2),
             modified_at: Utc::now() - chrono::Duration::days(2),
+            ..Default::default()
         });
         let bar = ProgressBar::new_spinner().with_message("none");
         let dup_fileset = select_duplicate_files(
             args.command.clone(),
-            DuplicateSelectionMethod::Oldest,
+            &DuplicateSelectionMethod::Oldest.into(),
+            &KeeperRules::default(),
             &"testhash".to_owned(),
+            HashAlgorithm::Md5,
             &files,
             1,
             1,
@@ -2128,232 +5741,1528 @@ mod tests {
         assert!(file2.is_some());
     }
 
-    #[test]
-    fn test_select_duplicate_files_empty_files() {
-        let args = create_default_command_line_arguments();
-        let files = Vec::new();
-        let bar = ProgressBar::new_spinner().with_message("none");
-        let dup_fileset = select_duplicate_files(
-            args.command.clone(),
-            DuplicateSelectionMethod::Oldest,
+    /// Select the file to keep from files, ranked by a --method value.
+    fn select_by(method: &str, files: &[FileInfo]) -> DuplicateFileSet {
+        let method: KeeperRanking = method.parse().unwrap();
+        select_duplicate_files(
+            Commands::Find {
+                method: method.clone(),
+            },
+            &method,
+            &KeeperRules::default(),
             &"testhash".to_owned(),
-            &files,
+            HashAlgorithm::Md5,
+            files,
             1,
             1,
-            &bar,
+            &ProgressBar::hidden(),
         )
-        .unwrap();
-        assert!(dup_fileset.keeper.is_none());
-        assert_eq!(dup_fileset.extras.len(), 0);
+        .unwrap()
     }
 
-    #[test]
-    fn test_process_a_duplicate_file_badfilepath() {
-        let mut args = create_default_command_line_arguments();
-        args.shared.dry_run = false;
-        let mut multi = MultiProgress::new();
-        // fake file
-        let file_info = FileInfo {
-            path: "xxx.xxx".to_string(),
-            size: 0,
-            created_at: Utc::now(),
-            modified_at: Utc::now(),
-        };
-        // use our mock file operators - returns ok for file operations
-        let file_ops = MockFileOperationsOk;
-        let result =
-            process_a_duplicate_file(&file_ops, &args, &file_info, "0000000000000000", &mut multi);
-        // FindCommand does not operate on the file, so it always returns Ok
-        assert!(result.is_ok());
+    /// The paths of the duplicates of a set, in the order they were ranked.
+    fn extra_paths(dup_fileset: &DuplicateFileSet) -> Vec<&str> {
+        dup_fileset
+            .extras
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect()
+    }
+
+    /// Files with the given paths, and the defaults for everything else.
+    fn files_at(paths: &[&str]) -> Vec<FileInfo> {
+        paths
+            .iter()
+            .map(|path| FileInfo {
+                path: path.to_string(),
+                ..Default::default()
+            })
+            .collect()
     }
 
     #[test]
-    fn test_process_a_duplicate_file_find() {
-        let mut args = create_default_command_line_arguments();
-        args.shared.dry_run = false;
-        let mut multi = MultiProgress::new();
-        // fake file
-        let file_info = FileInfo {
-            path: "xxx.xxx".to_string(),
-            size: 0,
-            created_at: Utc::now(),
-            modified_at: Utc::now(),
-        };
-        // use our mock file operators - returns ok for file operations
-        let file_ops = MockFileOperationsOk;
-        let result =
-            process_a_duplicate_file(&file_ops, &args, &file_info, "0000000000000000", &mut multi);
-        // FindCommand does not operate of the file, so it always returns Ok
-        assert!(result.is_ok());
+    fn test_select_duplicate_files_largest() {
+        let mut files = files_at(&["/data/a.txt", "/data/b.txt", "/data/c.txt"]);
+        for (file, size) in files.iter_mut().zip([10, 12, 11]) {
+            file.size = size;
+        }
+        let dup_fileset = select_by("largest", &files);
+        assert_eq!(dup_fileset.keeper.as_ref().unwrap().path, "/data/b.txt");
+        assert_eq!(
+            extra_paths(&dup_fileset),
+            vec!["/data/c.txt", "/data/a.txt"]
+        );
     }
 
     #[test]
-    fn test_process_a_duplicate_file_find_quiet() {
-        let mut args = create_default_command_line_arguments();
-        args.shared.dry_run = false;
-        args.shared.quiet = true;
-        let mut multi = MultiProgress::new();
-        // fake file
-        let file_info = FileInfo {
-            path: "xxx.xxx".to_string(),
-            size: 0,
-            created_at: Utc::now(),
-            modified_at: Utc::now(),
-        };
-        // use our mock file operators - returns ok for file operations
-        let file_ops = MockFileOperationsOk;
-        let result =
-            process_a_duplicate_file(&file_ops, &args, &file_info, "0000000000000000", &mut multi);
-        // FindCommand does not operate of the file, so it always returns Ok
-        assert!(result.is_ok());
+    fn test_select_duplicate_files_smallest() {
+        let mut files = files_at(&["/data/a.txt", "/data/b.txt", "/data/c.txt"]);
+        for (file, size) in files.iter_mut().zip([11, 12, 10]) {
+            file.size = size;
+        }
+        let dup_fileset = select_by("smallest", &files);
+        assert_eq!(dup_fileset.keeper.as_ref().unwrap().path, "/data/c.txt");
+        assert_eq!(
+            extra_paths(&dup_fileset),
+            vec!["/data/a.txt", "/data/b.txt"]
+        );
     }
 
     #[test]
-    fn test_process_a_duplicate_delete_badfilepath() {
-        let mut args = create_default_command_line_arguments();
-        args.shared.dry_run = false;
-        args.command = Commands::Delete {
-            method: DuplicateSelectionMethod::Newest,
-        };
-        let mut multi = MultiProgress::new();
-        // fake file
-        let file_info = FileInfo {
-            path: "xxx.xxx".to_string(),
-            size: 0,
-            created_at: Utc::now(),
-            modified_at: Utc::now(),
-        };
-        // use our mock file operators
-        let file_ops = MockFileOperationsError;
-        let result =
-            process_a_duplicate_file(&file_ops, &args, &file_info, "0000000000000000", &mut multi);
-        assert!(result.is_err());
+    fn test_select_duplicate_files_shortest_path() {
+        let files = files_at(&["/data/a.txt", "/data/archive/a.txt", "/d/a.txt"]);
+        let dup_fileset = select_by("shortest-path", &files);
+        assert_eq!(dup_fileset.keeper.as_ref().unwrap().path, "/d/a.txt");
+        assert_eq!(
+            extra_paths(&dup_fileset),
+            vec!["/data/a.txt", "/data/archive/a.txt"]
+        );
     }
 
     #[test]
-    fn test_process_a_duplicate_delete() {
-        let mut args = create_default_command_line_arguments();
-        args.shared.dry_run = false;
-        args.command = Commands::Delete {
-            method: DuplicateSelectionMethod::Newest,
-        };
-        let mut multi = MultiProgress::new();
-        // fake file
-        let file_info = FileInfo {
-            path: "xxx.xxx".to_string(),
-            size: 0,
-            created_at: Utc::now(),
-            modified_at: Utc::now(),
-        };
-        // use our mock file operators
-        let file_ops = MockFileOperationsOk;
-        let result =
-            process_a_duplicate_file(&file_ops, &args, &file_info, "0000000000000000", &mut multi);
-        assert!(result.is_ok());
+    fn test_select_duplicate_files_longest_path() {
+        let files = files_at(&["/data/a.txt", "/data/archive/a.txt", "/d/a.txt"]);
+        let dup_fileset = select_by("longest-path", &files);
+        assert_eq!(
+            dup_fileset.keeper.as_ref().unwrap().path,
+            "/data/archive/a.txt"
+        );
+        assert_eq!(extra_paths(&dup_fileset), vec!["/data/a.txt", "/d/a.txt"]);
+    }
+
+    #[test]
+    fn test_select_duplicate_files_shallowest() {
+        // the shallowest file does not have the shortest path
+        let files = files_at(&["/a/b/c.txt", "/a/a-much-longer-name.txt", "/a/b/c/d.txt"]);
+        let dup_fileset = select_by("shallowest", &files);
+        assert_eq!(
+            dup_fileset.keeper.as_ref().unwrap().path,
+            "/a/a-much-longer-name.txt"
+        );
+        assert_eq!(
+            extra_paths(&dup_fileset),
+            vec!["/a/b/c.txt", "/a/b/c/d.txt"]
+        );
+    }
+
+    #[test]
+    fn test_select_duplicate_files_alphabetical() {
+        let files = files_at(&["/data/c.txt", "/data/a.txt", "/data/b.txt"]);
+        let dup_fileset = select_by("alphabetical", &files);
+        assert_eq!(dup_fileset.keeper.as_ref().unwrap().path, "/data/a.txt");
+        assert_eq!(
+            extra_paths(&dup_fileset),
+            vec!["/data/b.txt", "/data/c.txt"]
+        );
+    }
+
+    #[test]
+    fn test_select_duplicate_files_most_hardlinks() {
+        let mut files = files_at(&["/data/a.txt", "/data/b.txt", "/data/c.txt"]);
+        for (file, links) in files.iter_mut().zip([1, 3, 2]) {
+            file.links = links;
+        }
+        let dup_fileset = select_by("most-hardlinks", &files);
+        assert_eq!(dup_fileset.keeper.as_ref().unwrap().path, "/data/b.txt");
+        assert_eq!(
+            extra_paths(&dup_fileset),
+            vec!["/data/c.txt", "/data/a.txt"]
+        );
+    }
+
+    #[test]
+    fn test_select_duplicate_files_prefer() {
+        let files = files_at(&["/data/a.txt", "/archive/2020/a.txt", "/backup/a.txt"]);
+        let dup_fileset = select_by("prefer:/archive", &files);
+        assert_eq!(
+            dup_fileset.keeper.as_ref().unwrap().path,
+            "/archive/2020/a.txt"
+        );
+        // the rest tie, so they are ranked by path
+        assert_eq!(
+            extra_paths(&dup_fileset),
+            vec!["/backup/a.txt", "/data/a.txt"]
+        );
+    }
+
+    #[test]
+    fn test_select_duplicate_files_avoid() {
+        let files = files_at(&["/tmp/a.txt", "/tmp/b.txt", "/data/a.txt"]);
+        let dup_fileset = select_by("avoid:/tmp", &files);
+        assert_eq!(dup_fileset.keeper.as_ref().unwrap().path, "/data/a.txt");
+        assert_eq!(extra_paths(&dup_fileset), vec!["/tmp/a.txt", "/tmp/b.txt"]);
+    }
+
+    #[test]
+    fn test_select_duplicate_files_ranking() {
+        let now = Utc::now();
+        let mut files = files_at(&[
+            "/archive/deep/a.txt",
+            "/archive/a.txt",
+            "/archive/new.txt",
+            "/data/a.txt",
+        ]);
+        for (file, days) in files.iter_mut().zip([2, 2, 0, 5]) {
+            file.modified_at = now - chrono::Duration::days(days);
+        }
+        // the archived files come first, then the oldest of them, then the shortest path of those
+        let dup_fileset = select_by("prefer:/archive,oldest,shortest-path", &files);
+        assert_eq!(dup_fileset.keeper.as_ref().unwrap().path, "/archive/a.txt");
+        assert_eq!(
+            extra_paths(&dup_fileset),
+            vec!["/archive/deep/a.txt", "/archive/new.txt", "/data/a.txt"]
+        );
+
+        // the order of the criteria matters
+        let dup_fileset = select_by("oldest,prefer:/archive", &files);
+        assert_eq!(dup_fileset.keeper.as_ref().unwrap().path, "/data/a.txt");
+
+        // files with the same modified time are kept by path
+        let dup_fileset = select_by("newest", &files[..2]);
+        assert_eq!(dup_fileset.keeper.as_ref().unwrap().path, "/archive/a.txt");
+    }
+
+    #[test]
+    fn test_keeper_ranking_from_str() {
+        let ranking: KeeperRanking = " Oldest, shortest-path ,avoid:/tmp/*".parse().unwrap();
+        assert_eq!(
+            ranking.criteria,
+            vec![
+                RankingCriterion::Method(DuplicateSelectionMethod::Oldest),
+                RankingCriterion::Method(DuplicateSelectionMethod::ShortestPath),
+                RankingCriterion::Avoid(glob::Pattern::new("/tmp/*").unwrap()),
+            ]
+        );
+        assert!("interactive"
+            .parse::<KeeperRanking>()
+            .unwrap()
+            .is_interactive());
+
+        let error = "oldest,biggest".parse::<KeeperRanking>().unwrap_err();
+        assert!(error.starts_with("unknown criterion 'biggest', expected one of newest, oldest,"));
+        assert!(error.ends_with("prefer:<WILDCARD> or avoid:<WILDCARD>"));
+        assert!("oldest,".parse::<KeeperRanking>().is_err());
+        assert_eq!(
+            "prefer:".parse::<KeeperRanking>().unwrap_err(),
+            "'prefer:' is missing a wildcard"
+        );
+        assert!("avoid:/tmp/[".parse::<KeeperRanking>().is_err());
+        assert_eq!(
+            "oldest,interactive".parse::<KeeperRanking>().unwrap_err(),
+            "interactive can not be combined with other criteria"
+        );
+
+        // the command line rejects unknown criteria with the same error
+        let args =
+            Args::try_parse_from(["dupefindr", "find", "--method", "prefer:/archive,oldest"])
+                .unwrap();
+        assert_eq!(
+            args.command,
+            Commands::Find {
+                method: "prefer:/archive,oldest".parse().unwrap()
+            }
+        );
+        let error = Args::try_parse_from(["dupefindr", "find", "--method", "biggest"]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ValueValidation);
+        assert!(error.to_string().contains("unknown criterion 'biggest'"));
+    }
+
+    #[test]
+    fn test_select_duplicate_files_rules() {
+        let file = |path: &str, days: i64| FileInfo {
+            path: path.to_string(),
+            modified_at: Utc::now() - chrono::Duration::days(days),
+            ..Default::default()
+        };
+        let files = vec![
+            file("/home/bob/Downloads/report.pdf", 0),
+            file("/data/report.pdf", 1),
+            file("/srv/master/report.pdf", 2),
+        ];
+        let bar = ProgressBar::new_spinner().with_message("none");
+        let select = |prefer: &[&str], avoid: &[&str]| {
+            let patterns = |wildcards: &[&str]| -> Vec<glob::Pattern> {
+                wildcards
+                    .iter()
+                    .map(|wildcard| glob::Pattern::new(wildcard).unwrap())
+                    .collect()
+            };
+            let method = KeeperRanking::from(DuplicateSelectionMethod::Newest)
+                .with_path_rules(&patterns(prefer), &patterns(avoid));
+            select_duplicate_files(
+                Commands::Find {
+                    method: DuplicateSelectionMethod::Newest.into(),
+                },
+                &method,
+                &KeeperRules::default(),
+                &"testhash".to_owned(),
+                HashAlgorithm::Md5,
+                &files,
+                1,
+                1,
+                &bar,
+            )
+            .unwrap()
+        };
+
+        // the path rules rank the files before the newest is chosen
+        let dup_fileset = select(&["/srv/master"], &[]);
+        assert_eq!(dup_fileset.keeper.unwrap().path, "/srv/master/report.pdf");
+        assert_eq!(dup_fileset.extras.len(), 2);
+
+        assert_eq!(
+            select(&[], &["/home/*/Downloads"]).keeper.unwrap().path,
+            "/data/report.pdf"
+        );
+
+        // avoiding a file wins over preferring it
+        assert_eq!(
+            select(&["/home/*/Downloads"], &["/home/*/Downloads"])
+                .keeper
+                .unwrap()
+                .path,
+            "/data/report.pdf"
+        );
+
+        // earlier preferred wildcards win
+        assert_eq!(
+            select(&["/data", "/srv/master"], &[]).keeper.unwrap().path,
+            "/data/report.pdf"
+        );
+
+        // when every copy is avoided, the set is skipped
+        let dup_fileset = select(&[], &["/home/*/Downloads", "/data", "/srv"]);
+        assert_eq!(dup_fileset.result, DuplicateResult::Skipped);
+        assert!(dup_fileset.keeper.is_none());
+        assert_eq!(dup_fileset.extras.len(), 3);
+        assert_eq!(
+            dup_fileset.reason.as_deref(),
+            Some("all 3 copies are avoided, so none can be kept")
+        );
+    }
+
+    #[test]
+    fn test_keeper_ranking_with_path_rules() {
+        let pattern = |wildcard: &str| glob::Pattern::new(wildcard).unwrap();
+        let ranking: KeeperRanking = "oldest".parse().unwrap();
+        let ranking = ranking.with_path_rules(&[pattern("/srv")], &[pattern("/tmp")]);
+        // --avoid and --prefer are the same as leading avoid: and prefer: criteria
+        assert_eq!(ranking, "avoid:/tmp,prefer:/srv,oldest".parse().unwrap());
+
+        // so a set is skipped when every copy is avoided, however the avoid criterion was given
+        let files = files_at(&["/tmp/a.txt", "/tmp/b.txt"]);
+        for method in ["avoid:/tmp", "newest,avoid:/tmp"] {
+            let dup_fileset = select_by(method, &files);
+            assert_eq!(dup_fileset.result, DuplicateResult::Skipped);
+            assert!(dup_fileset.keeper.is_none());
+        }
+
+        // interactive selection still applies after the path rules
+        let ranking = KeeperRanking::from(DuplicateSelectionMethod::Interactive)
+            .with_path_rules(&[], &[pattern("/tmp")]);
+        assert!(ranking.is_interactive());
+    }
+
+    #[test]
+    fn test_process_duplicates_protect() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.shared.no_verify = true;
+        args.shared.no_journal = true;
+        args.shared.protect = vec![glob::Pattern::new("/srv/master").unwrap()];
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+        let file = |path: &str, days: i64| FileInfo {
+            path: path.to_string(),
+            modified_at: Utc::now() - chrono::Duration::days(days),
+            ..Default::default()
+        };
+        let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        hash_map.insert(
+            "testhashkey".to_owned(),
+            vec![
+                file("/srv/master/a.txt", 2),
+                file("/data/a.txt", 1),
+                file("/data/b.txt", 0),
+            ],
+        );
+
+        // the protected file is kept instead of the newest
+        let file_ops = MockFileOperationsRecorder::default();
+        let results = process_duplicates(&file_ops, &args, &hash_map);
+        assert_eq!(
+            results[0].keeper.as_ref().unwrap().path,
+            "/srv/master/a.txt"
+        );
+        assert_eq!(
+            results[0].reason.as_deref(),
+            Some("/srv/master/a.txt is protected, so it was kept instead of /data/b.txt")
+        );
+        let mut modified = file_ops.modified.borrow().clone();
+        modified.sort();
+        assert_eq!(modified, vec!["/data/a.txt", "/data/b.txt"]);
+
+        // several protected files can not all be kept, so the set is skipped
+        args.shared
+            .protect
+            .push(glob::Pattern::new("/data/a.txt").unwrap());
+        let file_ops = MockFileOperationsRecorder::default();
+        let results = process_duplicates(&file_ops, &args, &hash_map);
+        assert_eq!(results[0].result, DuplicateResult::Skipped);
+        assert!(results[0].keeper.is_none());
+        assert_eq!(
+            results[0].reason.as_deref(),
+            Some(
+                "2 copies are protected (/data/a.txt, /srv/master/a.txt), so none can be modified"
+            )
+        );
+        assert!(file_ops.modified.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_process_duplicates_protect_hardlink() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.shared.no_verify = true;
+        args.shared.no_journal = true;
+        args.shared.protect = vec![glob::Pattern::new("/srv/master").unwrap()];
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+        let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        hash_map.insert(
+            "testhashkey".to_owned(),
+            vec![
+                // only a hardlink to this file is under the protected path
+                FileInfo {
+                    path: "/data/a.txt".to_string(),
+                    hardlinks: vec!["/srv/master/a.txt".to_string()],
+                    modified_at: Utc::now() - chrono::Duration::days(1),
+                    ..Default::default()
+                },
+                FileInfo {
+                    path: "/data/b.txt".to_string(),
+                    modified_at: Utc::now(),
+                    ..Default::default()
+                },
+            ],
+        );
+
+        // the file with the protected hardlink is kept, and none of its paths are touched
+        let file_ops = MockFileOperationsRecorder::default();
+        let results = process_duplicates(&file_ops, &args, &hash_map);
+        assert_eq!(results[0].keeper.as_ref().unwrap().path, "/data/a.txt");
+        assert_eq!(
+            results[0].reason.as_deref(),
+            Some("/data/a.txt has a protected hardlink /srv/master/a.txt, so it was kept instead of /data/b.txt")
+        );
+        assert_eq!(*file_ops.modified.borrow(), vec!["/data/b.txt"]);
+    }
+
+    #[test]
+    fn test_process_a_duplicate_file_protected() {
+        // a protected file in a plan is refused, rather than removed
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.shared.protect = vec![glob::Pattern::new("/srv/*").unwrap()];
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+        let file_info = FileInfo {
+            path: "/srv/master/a.txt".to_string(),
+            ..Default::default()
+        };
+        let file_ops = MockFileOperationsRecorder::default();
+        let result = process_a_duplicate_file(
+            &file_ops,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut MultiProgress::new(),
+        );
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert!(file_ops.modified.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_load_config() {
+        let temp_dir = tempdir().unwrap();
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(&config_path, "protect = [\"/srv/master\"]\n").unwrap();
+        let mut args = create_default_command_line_arguments();
+        args.shared.protect = vec![glob::Pattern::new("/home/*/Documents").unwrap()];
+        args.shared.config = Some(config_path.to_str().unwrap().to_string());
+
+        // the config adds to the paths protected on the command line
+        load_config(&mut args).unwrap();
+        let protected: Vec<&str> = args
+            .shared
+            .protect
+            .iter()
+            .map(|pattern| pattern.as_str())
+            .collect();
+        assert_eq!(protected, vec!["/home/*/Documents", "/srv/master"]);
+
+        std::fs::write(&config_path, "protect = [\"/srv/[master\"]\n").unwrap();
+        assert_eq!(
+            load_config(&mut args).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // a config file given on the command line must exist
+        args.shared.config = Some(
+            temp_dir
+                .path()
+                .join("missing.toml")
+                .to_str()
+                .unwrap()
+                .to_string(),
+        );
+        assert!(load_config(&mut args).is_err());
+    }
+
+    #[test]
+    fn test_start_search_avoid() {
+        let temp_dir = tempdir().unwrap();
+        let downloads = temp_dir.path().join("Downloads");
+        std::fs::create_dir(&downloads).unwrap();
+        std::fs::write(downloads.join("a.txt"), "dupe").unwrap();
+        std::fs::write(downloads.join("b.txt"), "dupe").unwrap();
+        let report_path = temp_dir.path().join("report.csv");
+        let mut args = create_default_command_line_arguments();
+        args.shared.path = temp_dir.path().to_str().unwrap().to_string();
+        args.shared.recursive = true;
+        args.shared.dry_run = false;
+        args.shared.no_journal = true;
+        args.shared.create_report = true;
+        args.shared.report_path = report_path.to_str().unwrap().to_string();
+        args.shared.avoid = vec![glob::Pattern::new("*/Downloads").unwrap()];
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+
+        // the set is reported, but nothing is deleted
+        let result = start_search(&RealFileOperations, &args).unwrap();
+        assert_eq!(result.number_duplicates, 1);
+        assert_eq!(result.total_size, 0);
+        assert!(downloads.join("a.txt").exists());
+        assert!(downloads.join("b.txt").exists());
+
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .from_path(&report_path)
+            .unwrap();
+        let headers = rdr.headers().unwrap().clone();
+        let column = |name: &str| headers.iter().position(|header| header == name).unwrap();
+        let rows: Vec<csv::StringRecord> = rdr.records().map(|row| row.unwrap()).collect();
+        assert_eq!(rows.len(), 2);
+        for row in &rows {
+            assert_eq!(&row[column("Result")], "Skipped");
+            assert_eq!(&row[column("Action")], "none");
+            assert_eq!(
+                &row[column("Reason")],
+                "all 2 copies are avoided, so none can be kept"
+            );
+        }
+    }
+
+    #[test]
+    fn test_files_other_than() {
+        let files = files_at(&["/data/a.txt", "/data/b.txt", "/data/c.txt"]);
+        // the file selected interactively is not among the files processed
+        let extras = files_other_than(&files, Some(&files[1]));
+        let paths: Vec<&str> = extras.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, vec!["/data/a.txt", "/data/c.txt"]);
+        assert_eq!(files_other_than(&files, None).len(), 3);
+    }
+
+    #[test]
+    fn test_select_duplicate_files_deterministic() {
+        let mut files: Vec<FileInfo> = ["/data/c.txt", "/data/a.txt", "/data/b.txt"]
+            .iter()
+            .map(|path| FileInfo {
+                path: path.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let bar = ProgressBar::new_spinner().with_message("none");
+        // the same file is kept whatever order the files were found in
+        for _ in 0..files.len() {
+            files.rotate_left(1);
+            let dup_fileset = select_duplicate_files(
+                Commands::Find {
+                    method: DuplicateSelectionMethod::ShortestPath.into(),
+                },
+                &DuplicateSelectionMethod::ShortestPath.into(),
+                &KeeperRules::default(),
+                &"testhash".to_owned(),
+                HashAlgorithm::Md5,
+                &files,
+                1,
+                1,
+                &bar,
+            )
+            .unwrap();
+            assert_eq!(dup_fileset.keeper.unwrap().path, "/data/a.txt");
+            let extras: Vec<&str> = dup_fileset
+                .extras
+                .iter()
+                .map(|file| file.path.as_str())
+                .collect();
+            assert_eq!(extras, vec!["/data/b.txt", "/data/c.txt"]);
+        }
+    }
+
+    #[test]
+    fn test_select_duplicate_files_empty_files() {
+        let args = create_default_command_line_arguments();
+        let files = Vec::new();
+        let bar = ProgressBar::new_spinner().with_message("none");
+        let dup_fileset = select_duplicate_files(
+            args.command.clone(),
+            &DuplicateSelectionMethod::Oldest.into(),
+            &KeeperRules::default(),
+            &"testhash".to_owned(),
+            HashAlgorithm::Md5,
+            &files,
+            1,
+            1,
+            &bar,
+        )
+        .unwrap();
+        assert!(dup_fileset.keeper.is_none());
+        assert_eq!(dup_fileset.extras.len(), 0);
+    }
+
+    #[test]
+    fn test_process_a_duplicate_file_badfilepath() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        let mut multi = MultiProgress::new();
+        // fake file
+        let file_info = FileInfo {
+            path: "xxx.xxx".to_string(),
+            size: 0,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            ..Default::default()
+        };
+        // use our mock file operators - returns ok for file operations
+        let file_ops = MockFileOperationsOk;
+        let result = process_a_duplicate_file(
+            &file_ops,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        // FindCommand does not operate on the file, so it always returns Ok
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_process_a_duplicate_file_find() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        let mut multi = MultiProgress::new();
+        // fake file
+        let file_info = FileInfo {
+            path: "xxx.xxx".to_string(),
+            size: 0,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            ..Default::default()
+        };
+        // use our mock file operators - returns ok for file operations
+        let file_ops = MockFileOperationsOk;
+        let result = process_a_duplicate_file(
+            &file_ops,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        // FindCommand does not operate of the file, so it always returns Ok
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_process_a_duplicate_file_find_quiet() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.shared.quiet = true;
+        let mut multi = MultiProgress::new();
+        // fake file
+        let file_info = FileInfo {
+            path: "xxx.xxx".to_string(),
+            size: 0,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            ..Default::default()
+        };
+        // use our mock file operators - returns ok for file operations
+        let file_ops = MockFileOperationsOk;
+        let result = process_a_duplicate_file(
+            &file_ops,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        // FindCommand does not operate of the file, so it always returns Ok
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_process_a_duplicate_delete_badfilepath() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+        let mut multi = MultiProgress::new();
+        // fake file
+        let file_info = FileInfo {
+            path: "xxx.xxx".to_string(),
+            size: 0,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            ..Default::default()
+        };
+        // use our mock file operators
+        let file_ops = MockFileOperationsError;
+        let result = process_a_duplicate_file(
+            &file_ops,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_process_a_duplicate_delete() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+        let mut multi = MultiProgress::new();
+        // fake file
+        let file_info = FileInfo {
+            path: "xxx.xxx".to_string(),
+            size: 0,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            ..Default::default()
+        };
+        // use our mock file operators
+        let file_ops = MockFileOperationsOk;
+        let result = process_a_duplicate_file(
+            &file_ops,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert!(result.is_ok());
     }
 
     #[test]
     fn test_process_a_duplicate_copy_badfilepath() {
         let mut args = create_default_command_line_arguments();
         args.shared.dry_run = false;
-        args.command = Commands::Copy {
-            location: "/bad/path".to_string(),
-            method: DuplicateSelectionMethod::Newest,
-            flatten: false,
-            no_hash_folder: false,
-            overwrite: false,
+        args.command = Commands::Copy {
+            location: "/bad/path".to_string(),
+            method: DuplicateSelectionMethod::Newest.into(),
+            flatten: false,
+            no_hash_folder: false,
+            overwrite: false,
+        };
+        let mut multi = MultiProgress::new();
+        // fake file
+        let file_info = FileInfo {
+            path: "xxx.xxx".to_string(),
+            size: 0,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            ..Default::default()
+        };
+        // use our mock file operators
+        let file_ops = MockFileOperationsError;
+        let result = process_a_duplicate_file(
+            &file_ops,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_process_a_duplicate_copy() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Copy {
+            location: "/bad/path".to_string(),
+            method: DuplicateSelectionMethod::Newest.into(),
+            flatten: false,
+            no_hash_folder: false,
+            overwrite: false,
+        };
+        let mut multi = MultiProgress::new();
+        // fake file
+        let file_info = FileInfo {
+            path: "xxx.xxx".to_string(),
+            size: 0,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            ..Default::default()
+        };
+        // use our mock file operators
+        let file_ops = MockFileOperationsOk;
+        let result = process_a_duplicate_file(
+            &file_ops,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_process_a_duplicate_move_badfilepath() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Move {
+            location: "/bad/path".to_string(),
+            method: DuplicateSelectionMethod::Newest.into(),
+            flatten: false,
+            no_hash_folder: false,
+            overwrite: false,
+        };
+        let mut multi = MultiProgress::new();
+        // fake file
+        let file_info = FileInfo {
+            path: "xxx.xxx".to_string(),
+            size: 0,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            ..Default::default()
+        };
+        // use our mock file operators
+        let file_ops = MockFileOperationsError;
+        let result = process_a_duplicate_file(
+            &file_ops,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_process_a_duplicate_move() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Move {
+            location: "/bad/path".to_string(),
+            method: DuplicateSelectionMethod::Newest.into(),
+            flatten: false,
+            no_hash_folder: false,
+            overwrite: false,
+        };
+        let mut multi = MultiProgress::new();
+        // fake file
+        let file_info = FileInfo {
+            path: "xxx.xxx".to_string(),
+            size: 0,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            ..Default::default()
+        };
+        // use our mock file operators
+        let file_ops = MockFileOperationsOk;
+        let result = process_a_duplicate_file(
+            &file_ops,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_process_a_duplicate_link() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Link {
+            method: DuplicateSelectionMethod::Newest.into(),
+        };
+        let mut multi = MultiProgress::new();
+        // fake files
+        let keeper = FileInfo {
+            path: "keeper.xxx".to_string(),
+            device: 1,
+            ..Default::default()
+        };
+        let file_info = FileInfo {
+            path: "xxx.xxx".to_string(),
+            device: 1,
+            ..Default::default()
+        };
+        let result = process_a_duplicate_file(
+            &MockFileOperationsOk,
+            &args,
+            &file_info,
+            Some(&keeper),
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert!(result.is_ok());
+        let result = process_a_duplicate_file(
+            &MockFileOperationsError,
+            &args,
+            &file_info,
+            Some(&keeper),
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert!(result.is_err());
+        // nothing to link to
+        let result = process_a_duplicate_file(
+            &MockFileOperationsOk,
+            &args,
+            &file_info,
+            None,
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_process_a_duplicate_link_cross_device() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Link {
+            method: DuplicateSelectionMethod::Newest.into(),
         };
         let mut multi = MultiProgress::new();
-        // fake file
+        let keeper = FileInfo {
+            path: "keeper.xxx".to_string(),
+            device: 1,
+            ..Default::default()
+        };
         let file_info = FileInfo {
             path: "xxx.xxx".to_string(),
-            size: 0,
-            created_at: Utc::now(),
-            modified_at: Utc::now(),
+            device: 2,
+            ..Default::default()
         };
-        // use our mock file operators
-        let file_ops = MockFileOperationsError;
-        let result =
-            process_a_duplicate_file(&file_ops, &args, &file_info, "0000000000000000", &mut multi);
+        let result = process_a_duplicate_file(
+            &MockFileOperationsOk,
+            &args,
+            &file_info,
+            Some(&keeper),
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
         assert!(result.is_err());
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_process_a_duplicate_copy() {
+    fn test_real_file_operations_hard_link() {
+        use std::os::unix::fs::MetadataExt;
+        let temp_dir = tempdir().unwrap();
+        let keeper = temp_dir.path().join("keeper.txt");
+        let duplicate = temp_dir.path().join("duplicate.txt");
+        std::fs::write(&keeper, "contents").unwrap();
+        std::fs::write(&duplicate, "contents").unwrap();
+
+        RealFileOperations
+            .hard_link(keeper.to_str().unwrap(), duplicate.to_str().unwrap())
+            .unwrap();
+        let keeper_metadata = std::fs::metadata(&keeper).unwrap();
+        let duplicate_metadata = std::fs::metadata(&duplicate).unwrap();
+        assert_eq!(keeper_metadata.ino(), duplicate_metadata.ino());
+        assert_eq!(keeper_metadata.nlink(), 2);
+        // the temporary link is gone
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_real_file_operations_hard_link_same_file() {
+        let temp_dir = tempdir().unwrap();
+        let keeper = temp_dir.path().join("keeper.txt");
+        std::fs::write(&keeper, "contents").unwrap();
+        let same_keeper = temp_dir.path().join(".").join("keeper.txt");
+
+        let result =
+            RealFileOperations.hard_link(keeper.to_str().unwrap(), same_keeper.to_str().unwrap());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(std::fs::read_to_string(&keeper).unwrap(), "contents");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_process_a_duplicate_symlink() {
         let mut args = create_default_command_line_arguments();
         args.shared.dry_run = false;
-        args.command = Commands::Copy {
-            location: "/bad/path".to_string(),
-            method: DuplicateSelectionMethod::Newest,
-            flatten: false,
-            no_hash_folder: false,
-            overwrite: false,
+        args.command = Commands::Symlink {
+            method: DuplicateSelectionMethod::Newest.into(),
+            relative: false,
         };
         let mut multi = MultiProgress::new();
-        // fake file
+        // fake files, symlinks may cross filesystems
+        let keeper = FileInfo {
+            path: "keeper.xxx".to_string(),
+            device: 1,
+            ..Default::default()
+        };
         let file_info = FileInfo {
             path: "xxx.xxx".to_string(),
-            size: 0,
-            created_at: Utc::now(),
-            modified_at: Utc::now(),
+            device: 2,
+            ..Default::default()
         };
-        // use our mock file operators
-        let file_ops = MockFileOperationsOk;
-        let result =
-            process_a_duplicate_file(&file_ops, &args, &file_info, "0000000000000000", &mut multi);
+        let result = process_a_duplicate_file(
+            &MockFileOperationsOk,
+            &args,
+            &file_info,
+            Some(&keeper),
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
         assert!(result.is_ok());
+        let result = process_a_duplicate_file(
+            &MockFileOperationsError,
+            &args,
+            &file_info,
+            Some(&keeper),
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert!(result.is_err());
     }
 
     #[test]
-    fn test_process_a_duplicate_move_badfilepath() {
+    fn test_relative_path() {
+        assert_eq!(
+            relative_path(Path::new("/a/b/c"), Path::new("/a/d/file.txt")),
+            Path::new("../../d/file.txt")
+        );
+        assert_eq!(
+            relative_path(Path::new("/a"), Path::new("/a/file.txt")),
+            Path::new("file.txt")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_real_file_operations_symlink() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        let keeper = temp_dir.path().join("keeper.txt");
+        let absolute = temp_dir.path().join("absolute.txt");
+        let relative = temp_dir.path().join("sub").join("relative.txt");
+        for path in [&keeper, &absolute, &relative] {
+            std::fs::write(path, "contents").unwrap();
+        }
+
+        let keeper_path = keeper.to_str().unwrap();
+        RealFileOperations
+            .symlink(keeper_path, absolute.to_str().unwrap(), false)
+            .unwrap();
+        RealFileOperations
+            .symlink(keeper_path, relative.to_str().unwrap(), true)
+            .unwrap();
+        assert!(std::fs::read_link(&absolute).unwrap().is_absolute());
+        assert_eq!(
+            std::fs::read_link(&relative).unwrap(),
+            Path::new("../keeper.txt")
+        );
+        assert_eq!(std::fs::read_to_string(&relative).unwrap(), "contents");
+        // the backups are gone
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 3);
+        assert_eq!(
+            std::fs::read_dir(temp_dir.path().join("sub"))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_real_file_operations_symlink_missing_keeper() {
+        let temp_dir = tempdir().unwrap();
+        let duplicate = temp_dir.path().join("duplicate.txt");
+        std::fs::write(&duplicate, "contents").unwrap();
+        let keeper = temp_dir.path().join("missing.txt");
+        assert!(RealFileOperations
+            .symlink(keeper.to_str().unwrap(), duplicate.to_str().unwrap(), false)
+            .is_err());
+        // the original is left in place
+        assert_eq!(std::fs::read_to_string(&duplicate).unwrap(), "contents");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_real_file_operations_symlink_same_file() {
+        let temp_dir = tempdir().unwrap();
+        let keeper = temp_dir.path().join("keeper.txt");
+        std::fs::write(&keeper, "contents").unwrap();
+        let same_keeper = temp_dir.path().join(".").join("keeper.txt");
+
+        let result = RealFileOperations.symlink(
+            keeper.to_str().unwrap(),
+            same_keeper.to_str().unwrap(),
+            false,
+        );
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(!std::fs::symlink_metadata(&keeper).unwrap().is_symlink());
+        assert_eq!(std::fs::read_to_string(&keeper).unwrap(), "contents");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_replace_with_backup_restores_original() {
+        let temp_dir = tempdir().unwrap();
+        let duplicate = temp_dir.path().join("duplicate.txt");
+        std::fs::write(&duplicate, "contents").unwrap();
+        let backup = temp_dir.path().join(".duplicate.txt.dupefindr-backup");
+
+        // the replacement fails after the original has been moved out of the way
+        let result = replace_with_backup(&duplicate, |destination| {
+            assert!(!destination.exists());
+            assert!(backup.exists());
+            std::fs::write(destination, "partial").unwrap();
+            Err(io::Error::other("link failed"))
+        });
+        assert_eq!(result.unwrap_err().to_string(), "link failed");
+        assert_eq!(std::fs::read_to_string(&duplicate).unwrap(), "contents");
+        assert!(!backup.exists());
+
+        // a replacement that succeeds removes the backup
+        replace_with_backup(&duplicate, |destination| {
+            std::fs::write(destination, "replaced")
+        })
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&duplicate).unwrap(), "replaced");
+        assert!(!backup.exists());
+
+        // a file already using the name of the backup is left alone
+        std::fs::write(&backup, "other").unwrap();
+        replace_with_backup(&duplicate, |destination| {
+            std::fs::write(destination, "replaced again")
+        })
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&duplicate).unwrap(),
+            "replaced again"
+        );
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "other");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_process_a_duplicate_reflink() {
         let mut args = create_default_command_line_arguments();
         args.shared.dry_run = false;
-        args.command = Commands::Move {
-            location: "/bad/path".to_string(),
-            method: DuplicateSelectionMethod::Newest,
-            flatten: false,
-            no_hash_folder: false,
-            overwrite: false,
+        args.command = Commands::Reflink {
+            method: DuplicateSelectionMethod::Newest.into(),
         };
         let mut multi = MultiProgress::new();
-        // fake file
+        // fake files
+        let keeper = FileInfo {
+            path: "keeper.xxx".to_string(),
+            ..Default::default()
+        };
         let file_info = FileInfo {
             path: "xxx.xxx".to_string(),
-            size: 0,
-            created_at: Utc::now(),
-            modified_at: Utc::now(),
+            ..Default::default()
         };
-        // use our mock file operators
-        let file_ops = MockFileOperationsError;
-        let result =
-            process_a_duplicate_file(&file_ops, &args, &file_info, "0000000000000000", &mut multi);
+        let result = process_a_duplicate_file(
+            &MockFileOperationsOk,
+            &args,
+            &file_info,
+            Some(&keeper),
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
+        assert_eq!(result.unwrap().bytes_deduplicated, 4096);
+        let result = process_a_duplicate_file(
+            &MockFileOperationsError,
+            &args,
+            &file_info,
+            Some(&keeper),
+            None,
+            "0000000000000000",
+            &mut multi,
+        );
         assert!(result.is_err());
     }
 
     #[test]
-    fn test_process_a_duplicate_move() {
+    fn test_process_duplicates_reflink() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Reflink {
+            method: DuplicateSelectionMethod::Newest.into(),
+        };
+        let files: Vec<FileInfo> = (0..3)
+            .map(|index| FileInfo {
+                path: format!("file{}.txt", index),
+                modified_at: Utc::now() - chrono::Duration::days(index),
+                ..Default::default()
+            })
+            .collect();
+        let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        hash_map.insert("testhashkey".to_owned(), files);
+
+        let result = process_duplicates(&MockFileOperationsOk, &args, &hash_map);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].result, DuplicateResult::Reflinked);
+        assert_eq!(result[0].bytes_deduplicated, 2 * 4096);
+    }
+
+    #[test]
+    fn test_process_duplicates_quarantine() {
+        let temp_dir = tempdir().unwrap();
+        let quarantine_dir = temp_dir.path().join("quarantine");
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Quarantine {
+            location: quarantine_dir.to_str().unwrap().to_string(),
+            method: DuplicateSelectionMethod::Newest.into(),
+        };
+
+        // duplicates with the same name in different folders
+        let mut files: Vec<FileInfo> = Vec::new();
+        for (index, folder) in ["a", "b", "c"].iter().enumerate() {
+            let path = temp_dir.path().join(folder).join("file.txt");
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "contents").unwrap();
+            files.push(FileInfo {
+                path: path.to_str().unwrap().to_string(),
+                size: 8,
+                created_at: Utc::now() - chrono::Duration::days(index as i64),
+                modified_at: Utc::now() - chrono::Duration::days(index as i64),
+                ..Default::default()
+            });
+        }
+        let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        hash_map.insert("testhashkey".to_owned(), files);
+
+        let result = process_duplicates(&RealFileOperations, &args, &hash_map);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].result, DuplicateResult::Quarantined);
+
+        let manifest = Manifest::load(&quarantine_dir).unwrap();
+        assert_eq!(manifest.entries().len(), 2);
+        for entry in manifest.entries() {
+            assert!(!Path::new(&entry.original_path).exists());
+            assert!(Path::new(&entry.quarantined_path).exists());
+            assert!(entry
+                .keeper_path
+                .ends_with(&format!("a{}file.txt", std::path::MAIN_SEPARATOR)));
+            assert_eq!(entry.hash, "testhashkey");
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_process_duplicates_quarantine_cross_device() {
+        let temp_dir = tempdir().unwrap();
+        let quarantine_dir = temp_dir.path().join("quarantine");
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Quarantine {
+            location: quarantine_dir.to_str().unwrap().to_string(),
+            method: DuplicateSelectionMethod::Newest.into(),
+        };
+
+        let mut files: Vec<FileInfo> = Vec::new();
+        for (index, name) in ["a.txt", "b.txt"].iter().enumerate() {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, "contents").unwrap();
+            files.push(FileInfo {
+                path: path.to_str().unwrap().to_string(),
+                size: 8,
+                created_at: Utc::now() - chrono::Duration::days(index as i64),
+                modified_at: Utc::now() - chrono::Duration::days(index as i64),
+                ..Default::default()
+            });
+        }
+        let extra = temp_dir.path().join("b.txt");
+        let modified = fs::metadata(&extra).unwrap().modified().unwrap();
+        let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        hash_map.insert("testhashkey".to_owned(), files);
+
+        // the rename fails as if the quarantine was on another filesystem, so the file is copied
+        let result = process_duplicates(&MockFileOperationsCrossDevice, &args, &hash_map);
+        assert_eq!(result[0].result, DuplicateResult::Quarantined);
+        let manifest = Manifest::load(&quarantine_dir).unwrap();
+        assert_eq!(manifest.entries().len(), 1);
+        let quarantined = Path::new(&manifest.entries()[0].quarantined_path);
+        assert!(!extra.exists());
+        assert_eq!(fs::read_to_string(quarantined).unwrap(), "contents");
+        assert_eq!(
+            fs::metadata(quarantined).unwrap().modified().unwrap(),
+            modified
+        );
+    }
+
+    #[test]
+    fn test_process_duplicates_journal() {
+        let temp_dir = tempdir().unwrap();
+        let journal_path = temp_dir.path().join("journal.jsonl");
         let mut args = create_default_command_line_arguments();
         args.shared.dry_run = false;
+        args.shared.no_verify = true;
+        args.shared.no_journal = false;
+        args.shared.journal = Some(journal_path.to_str().unwrap().to_string());
         args.command = Commands::Move {
-            location: "/bad/path".to_string(),
-            method: DuplicateSelectionMethod::Newest,
-            flatten: false,
-            no_hash_folder: false,
+            location: "/tmp/moved".to_string(),
+            method: DuplicateSelectionMethod::Newest.into(),
+            flatten: true,
+            no_hash_folder: true,
             overwrite: false,
         };
-        let mut multi = MultiProgress::new();
-        // fake file
-        let file_info = FileInfo {
-            path: "xxx.xxx".to_string(),
-            size: 0,
-            created_at: Utc::now(),
-            modified_at: Utc::now(),
+        let files: Vec<FileInfo> = (0..3)
+            .map(|index| FileInfo {
+                path: format!("file{}.txt", index),
+                modified_at: Utc::now() - chrono::Duration::days(index),
+                ..Default::default()
+            })
+            .collect();
+        let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        hash_map.insert("testhashkey".to_owned(), files);
+
+        process_duplicates(&MockFileOperationsOk, &args, &hash_map);
+        let mut entries = Journal::load(&journal_path).unwrap();
+        entries.sort_by(|a, b| a.source.cmp(&b.source));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].operation, JournalOperation::Move);
+        assert_eq!(entries[0].source, "file1.txt");
+        assert_eq!(
+            entries[0].destination.as_deref(),
+            Some("/tmp/moved/file1.txt")
+        );
+        assert_eq!(entries[1].hash, "testhashkey");
+
+        // nothing is recorded without a journal
+        std::fs::remove_file(&journal_path).unwrap();
+        args.shared.no_journal = true;
+        process_duplicates(&MockFileOperationsOk, &args, &hash_map);
+        assert!(!journal_path.exists());
+    }
+
+    #[test]
+    fn test_start_search_plan_out() {
+        let (temp_dir, mut args) = create_duplicate_files();
+        let plan_path = temp_dir.path().join("plan.json");
+        args.shared.dry_run = false;
+        args.shared.plan_out = Some(plan_path.to_str().unwrap().to_string());
+
+        let results = start_search(&RealFileOperations, &args).unwrap();
+        assert_eq!(results.number_duplicates, 1);
+        // nothing is deleted while planning
+        for index in 0..3 {
+            assert!(temp_dir.path().join(format!("file{}.txt", index)).exists());
+        }
+        let plan = Plan::load(&plan_path).unwrap();
+        assert_eq!(plan.root, args.shared.path);
+        assert_eq!(plan.sets.len(), 1);
+        assert_eq!(plan.sets[0].action, PlanAction::Delete { to_trash: false });
+        assert_eq!(plan.sets[0].extras.len(), 2);
+
+        // find records the sets with no action
+        args.command = Commands::Find {
+            method: DuplicateSelectionMethod::Newest.into(),
         };
-        // use our mock file operators
-        let file_ops = MockFileOperationsOk;
-        let result =
-            process_a_duplicate_file(&file_ops, &args, &file_info, "0000000000000000", &mut multi);
-        assert!(result.is_ok());
+        start_search(&RealFileOperations, &args).unwrap();
+        let plan = Plan::load(&plan_path).unwrap();
+        assert_eq!(plan.sets.len(), 1);
+        assert_eq!(plan.sets[0].action, PlanAction::None);
+
+        // applying a plan with nothing to do is refused, rather than reporting no changes
+        args.shared.plan_out = None;
+        let code = run_apply_command(
+            &MockFileOperationsError,
+            &args,
+            plan_path.to_str().unwrap(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(code, ExitCode::UsageError);
+    }
+
+    #[test]
+    fn test_apply_plan_skips_changed_files() {
+        let (temp_dir, mut args) = create_duplicate_files();
+        let plan_path = temp_dir.path().join("plan.json");
+        args.shared.dry_run = false;
+        args.shared.plan_out = Some(plan_path.to_str().unwrap().to_string());
+        start_search(&RealFileOperations, &args).unwrap();
+        let plan = Plan::load(&plan_path).unwrap();
+        let set = &plan.sets[0];
+
+        // one duplicate changes after the plan is made
+        std::fs::write(&set.extras[0].path, "changed").unwrap();
+
+        args.shared.plan_out = None;
+        args.command = Commands::Apply {
+            plan: plan_path.to_str().unwrap().to_string(),
+            verify_hash: true,
+        };
+        let summary = apply_plan(&RealFileOperations, &args, &plan, true);
+        assert_eq!(summary.applied, 1);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.failed, 0);
+        assert!(Path::new(&set.keeper.path).exists());
+        assert!(Path::new(&set.extras[0].path).exists());
+        assert!(!Path::new(&set.extras[1].path).exists());
+
+        // a changed keeper skips the whole set
+        std::fs::write(&set.keeper.path, "changed keeper").unwrap();
+        let summary = apply_plan(&MockFileOperationsOk, &args, &plan, false);
+        assert_eq!(summary.applied, 0);
+        assert_eq!(summary.skipped, 2);
+    }
+
+    #[test]
+    fn test_planned_command_round_trip() {
+        let commands = vec![
+            Commands::Move {
+                location: "/tmp/moved".to_string(),
+                method: DuplicateSelectionMethod::Newest.into(),
+                flatten: true,
+                no_hash_folder: false,
+                overwrite: true,
+            },
+            Commands::Delete {
+                method: DuplicateSelectionMethod::Newest.into(),
+                to_trash: true,
+            },
+            Commands::Symlink {
+                method: DuplicateSelectionMethod::Newest.into(),
+                relative: true,
+            },
+            Commands::Quarantine {
+                location: "/tmp/quarantine".to_string(),
+                method: DuplicateSelectionMethod::Newest.into(),
+            },
+        ];
+        for command in commands {
+            assert_eq!(planned_command(&planned_action(&command)), Some(command));
+        }
+        assert_eq!(planned_command(&PlanAction::None), None);
+    }
+
+    #[test]
+    fn test_process_duplicates_outcomes() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.shared.no_verify = true;
+        args.command = Commands::Move {
+            location: "/tmp/moved".to_string(),
+            method: DuplicateSelectionMethod::Newest.into(),
+            flatten: true,
+            no_hash_folder: true,
+            overwrite: false,
+        };
+        let files: Vec<FileInfo> = (0..3)
+            .map(|index| FileInfo {
+                path: format!("file{}.txt", index),
+                modified_at: Utc::now() - chrono::Duration::days(index),
+                ..Default::default()
+            })
+            .collect();
+        let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        hash_map.insert("testhashkey".to_owned(), files);
+
+        let results = process_duplicates(&MockFileOperationsOk, &args, &hash_map);
+        let mut outcomes = results[0].outcomes.clone();
+        outcomes.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[0].path, "file1.txt");
+        assert_eq!(
+            outcomes[0].destination.as_deref(),
+            Some("/tmp/moved/file1.txt")
+        );
+        assert!(!outcomes[0].failed());
+
+        let results = process_duplicates(&MockFileOperationsError, &args, &hash_map);
+        assert_eq!(results[0].outcomes.len(), 2);
+        for outcome in &results[0].outcomes {
+            assert!(outcome.failed());
+            assert_eq!(outcome.destination, None);
+            assert!(outcome.error_kind.is_some());
+        }
+    }
+
+    #[test]
+    fn test_search_results_exit_code() {
+        let mut results = SearchResults {
+            number_duplicates: 0,
+            total_size: 0,
+            bytes_deduplicated: 0,
+            failures: vec![],
+        };
+        assert_eq!(results.exit_code(), ExitCode::NoDuplicates);
+        // any number of sets is reported the same way, so it can not wrap around to 0
+        results.number_duplicates = 256;
+        assert_eq!(results.exit_code(), ExitCode::DuplicatesFound);
+        assert_eq!(results.exit_code() as i32, 1);
+        results.failures.push(FileOutcome {
+            path: "file1.txt".to_string(),
+            error_kind: Some(io::ErrorKind::PermissionDenied),
+            error: Some("Permission denied".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(results.exit_code(), ExitCode::CompletedWithErrors);
+        assert_eq!(results.exit_code() as i32, 2);
     }
 
     #[test]
@@ -2362,7 +7271,7 @@ mod tests {
         args.shared.dry_run = false;
         args.command = Commands::Move {
             location: "/bad/path".to_string(),
-            method: DuplicateSelectionMethod::Newest,
+            method: DuplicateSelectionMethod::Newest.into(),
             flatten: false,
             no_hash_folder: false,
             overwrite: false,
@@ -2377,12 +7286,14 @@ mod tests {
             size: 1024,
             created_at: Utc::now(),
             modified_at: Utc::now(),
+            ..Default::default()
         });
         files.push(FileInfo {
             path: format!("{}//testdupe2.txt", args.shared.path.clone()),
             size: 1024,
             created_at: Utc::now(),
             modified_at: Utc::now(),
+            ..Default::default()
         });
         hash_map.insert("testhashkey".to_owned(), files);
 
@@ -2396,6 +7307,123 @@ mod tests {
         assert_eq!(result[0].result, DuplicateResult::Moved);
     }
 
+    #[test]
+    fn test_files_are_identical() {
+        let args = create_default_command_line_arguments();
+        let result = files_are_identical(
+            &format!("{}//testdupe1.txt", args.shared.path),
+            &format!("{}//testdupe2.txt", args.shared.path),
+            false,
+        );
+        assert!(result.unwrap());
+        // same size, different contents
+        let temp_dir = tempdir().unwrap();
+        let file1 = temp_dir.path().join("file1.bin");
+        let file2 = temp_dir.path().join("file2.bin");
+        std::fs::write(&file1, b"same size 1").unwrap();
+        std::fs::write(&file2, b"same size 2").unwrap();
+        let result = files_are_identical(file1.to_str().unwrap(), file2.to_str().unwrap(), false);
+        assert!(!result.unwrap());
+        // different sizes
+        let result = files_are_identical(
+            &format!("{}//testdupe1.txt", args.shared.path),
+            &format!("{}//testnodupe.txt", args.shared.path),
+            false,
+        );
+        assert!(!result.unwrap());
+        // missing file
+        let result = files_are_identical(
+            &format!("{}//testdupe1.txt", args.shared.path),
+            &format!("{}//testdupe1-notfound.txt", args.shared.path),
+            false,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_should_verify() {
+        let mut args = create_default_command_line_arguments();
+        assert!(!should_verify(&args));
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+        assert!(should_verify(&args));
+        args.shared.no_verify = true;
+        assert!(!should_verify(&args));
+        args.shared.no_verify = false;
+        args.command = Commands::Copy {
+            location: "/tmp".to_string(),
+            method: DuplicateSelectionMethod::Newest.into(),
+            flatten: false,
+            no_hash_folder: false,
+            overwrite: false,
+        };
+        assert!(!should_verify(&args));
+        args.shared.verify = true;
+        assert!(should_verify(&args));
+        args.command = Commands::Link {
+            method: DuplicateSelectionMethod::Newest.into(),
+        };
+        assert!(should_verify(&args));
+        args.command = Commands::Symlink {
+            method: DuplicateSelectionMethod::Newest.into(),
+            relative: true,
+        };
+        assert!(should_verify(&args));
+        args.shared.verify = false;
+        args.command = Commands::Reflink {
+            method: DuplicateSelectionMethod::Newest.into(),
+        };
+        assert!(!should_verify(&args));
+    }
+
+    #[test]
+    fn test_process_duplicates_delete_mismatched() {
+        let mut args = create_default_command_line_arguments();
+        args.shared.dry_run = false;
+        args.command = Commands::Delete {
+            method: DuplicateSelectionMethod::Newest.into(),
+            to_trash: false,
+        };
+
+        // the files share a (fake) hash, but their contents differ
+        let temp_dir = tempdir().unwrap();
+        let mut files: Vec<FileInfo> = Vec::new();
+        for (index, contents) in ["contents 1", "contents 2", "contents 1"]
+            .iter()
+            .enumerate()
+        {
+            let path = temp_dir.path().join(format!("file{}.txt", index));
+            std::fs::write(&path, contents).unwrap();
+            files.push(FileInfo {
+                path: path.to_str().unwrap().to_string(),
+                size: 10,
+                created_at: Utc::now() - chrono::Duration::days(index as i64),
+                modified_at: Utc::now() - chrono::Duration::days(index as i64),
+                ..Default::default()
+            });
+        }
+        let mut hash_map: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        hash_map.insert("testhashkey".to_owned(), files);
+
+        let file_ops = MockFileOperationsOk;
+        let result = process_duplicates(&file_ops, &args, &hash_map);
+        assert_eq!(result.len(), 2);
+        let mismatched = result
+            .iter()
+            .find(|dup_fileset| dup_fileset.result == DuplicateResult::Mismatched)
+            .unwrap();
+        assert_eq!(mismatched.extras.len(), 1);
+        assert!(mismatched.extras[0].path.ends_with("file1.txt"));
+        let deleted = result
+            .iter()
+            .find(|dup_fileset| dup_fileset.result == DuplicateResult::Deleted)
+            .unwrap();
+        assert_eq!(deleted.extras.len(), 1);
+        assert!(deleted.extras[0].path.ends_with("file2.txt"));
+    }
+
     #[test]
     fn test_terminal_guard() {
         // Create an instance of TerminalGuard that will be dropped when main exits
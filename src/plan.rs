@@ -0,0 +1,255 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::hasher::HashAlgorithm;
+
+/// The version of the plan format written by this build.
+pub const PLAN_VERSION: u32 = 1;
+
+/// # PlanAction
+/// What a plan will do with the duplicates of a set.  The action can be edited in the plan file before
+/// it is applied.
+///
+/// * `None` - Leave the duplicates alone.
+/// * `Move` - Move the duplicates to a location.
+/// * `Copy` - Copy the duplicates to a location.
+/// * `Delete` - Delete the duplicates, or move them to the trash.
+/// * `Link` - Replace the duplicates with hardlinks to the keeper.
+/// * `Symlink` - Replace the duplicates with symbolic links to the keeper.
+/// * `Reflink` - Share the storage of the duplicates with the keeper.
+/// * `Quarantine` - Move the duplicates to a quarantine folder.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PlanAction {
+    None,
+    Move {
+        location: String,
+        flatten: bool,
+        no_hash_folder: bool,
+        overwrite: bool,
+    },
+    Copy {
+        location: String,
+        flatten: bool,
+        no_hash_folder: bool,
+        overwrite: bool,
+    },
+    Delete {
+        to_trash: bool,
+    },
+    Link,
+    Symlink {
+        relative: bool,
+    },
+    Reflink,
+    Quarantine {
+        location: String,
+    },
+}
+
+/// # PlannedFile
+/// A file as it was when the plan was made.
+///
+/// * `path` - The path of the file.
+/// * `size` - The size of the file in bytes.
+/// * `created_at` - When the file was created.
+/// * `modified_at` - When the file was last modified.
+/// * `hardlinks` - Other paths that are hardlinks to the same file, which are acted on with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedFile {
+    pub path: String,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hardlinks: Vec<String>,
+}
+
+impl PlannedFile {
+    /// Check the file, and its hardlinks, still have the size and modified time recorded in the plan.
+    /// Returns the reason the file has changed.
+    pub fn check(&self) -> Result<(), String> {
+        for path in std::iter::once(&self.path).chain(&self.hardlinks) {
+            let metadata =
+                fs::metadata(path).map_err(|e| format!("{} is unreadable: {}", path, e))?;
+            if metadata.len() != self.size {
+                return Err(format!(
+                    "{} is now {} bytes, not {}",
+                    path,
+                    metadata.len(),
+                    self.size
+                ));
+            }
+            let modified_at = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .and_then(|since_epoch| chrono::Duration::from_std(since_epoch).ok())
+                .map(|since_epoch| DateTime::<Utc>::from(UNIX_EPOCH) + since_epoch);
+            if modified_at != Some(self.modified_at) {
+                return Err(format!("{} was modified after the plan was made", path));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// # PlannedSet
+/// A set of duplicates and what will be done with them.
+///
+/// * `hash` - The hash shared by the files.
+/// * `action` - What will be done with the duplicates.
+/// * `keeper` - The file to keep.
+/// * `extras` - The duplicates of the keeper.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedSet {
+    pub hash: String,
+    pub action: PlanAction,
+    pub keeper: PlannedFile,
+    pub extras: Vec<PlannedFile>,
+}
+
+/// # Plan
+/// The duplicates found by a search and what to do with them, written as JSON so it can be reviewed,
+/// edited and applied later.
+///
+/// * `version` - The version of the plan format.
+/// * `created_at` - When the plan was made.
+/// * `root` - The folder that was searched.  Moved and copied files keep their paths relative to it.
+/// * `algorithm` - The algorithm used to hash the files.
+/// * `normalize_line_endings` - CRLF and LF line endings were treated as equal.
+/// * `sets` - The sets of duplicates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub root: String,
+    pub algorithm: HashAlgorithm,
+    pub normalize_line_endings: bool,
+    pub sets: Vec<PlannedSet>,
+}
+
+impl Plan {
+    /// Read a plan file.
+    pub fn load(path: &Path) -> Result<Plan, io::Error> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        let plan: Plan = serde_json::from_reader(file).map_err(io::Error::other)?;
+        if plan.version != PLAN_VERSION {
+            return Err(io::Error::other(format!(
+                "Unsupported plan version {}, expected {}",
+                plan.version, PLAN_VERSION
+            )));
+        }
+        Ok(plan)
+    }
+
+    /// True if any set of the plan has an action other than none.
+    pub fn has_actions(&self) -> bool {
+        self.sets.iter().any(|set| set.action != PlanAction::None)
+    }
+
+    /// Write the plan to a file.
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        let contents = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+}
+
+/// # ApplySummary
+/// What happened when a plan was applied.
+///
+/// * `applied` - The number of files acted on.
+/// * `skipped` - The number of files left alone because they changed after the plan was made.
+/// * `failed` - The number of files the action failed on.
+#[derive(Debug, Default)]
+pub struct ApplySummary {
+    pub applied: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn planned_file(path: &Path) -> PlannedFile {
+        let metadata = fs::metadata(path).unwrap();
+        PlannedFile {
+            path: path.to_str().unwrap().to_string(),
+            size: metadata.len(),
+            created_at: Utc::now(),
+            modified_at: DateTime::<Utc>::from(UNIX_EPOCH)
+                + chrono::Duration::from_std(
+                    metadata
+                        .modified()
+                        .unwrap()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap(),
+                )
+                .unwrap(),
+            hardlinks: vec![],
+        }
+    }
+
+    #[test]
+    fn test_plan_save_and_load() {
+        let temp_dir = tempdir().unwrap();
+        let keeper_path = temp_dir.path().join("keeper.txt");
+        let extra_path = temp_dir.path().join("extra.txt");
+        fs::write(&keeper_path, "duplicate").unwrap();
+        fs::write(&extra_path, "duplicate").unwrap();
+        let plan = Plan {
+            version: PLAN_VERSION,
+            created_at: Utc::now(),
+            root: temp_dir.path().to_str().unwrap().to_string(),
+            algorithm: HashAlgorithm::Blake3,
+            normalize_line_endings: false,
+            sets: vec![PlannedSet {
+                hash: "abc".to_string(),
+                action: PlanAction::Move {
+                    location: "/tmp/moved".to_string(),
+                    flatten: true,
+                    no_hash_folder: false,
+                    overwrite: false,
+                },
+                keeper: planned_file(&keeper_path),
+                extras: vec![planned_file(&extra_path)],
+            }],
+        };
+        let plan_path = temp_dir.path().join("plan.json");
+        plan.save(&plan_path).unwrap();
+        assert_eq!(Plan::load(&plan_path).unwrap(), plan);
+
+        // actions are tagged by type, so they can be edited by hand
+        let contents = fs::read_to_string(&plan_path).unwrap();
+        assert!(contents.contains("\"type\": \"move\""));
+        let edited = contents.replace(
+            "\"type\": \"move\",",
+            "\"type\": \"delete\", \"to_trash\": true,",
+        );
+        fs::write(&plan_path, edited).unwrap();
+        assert_eq!(
+            Plan::load(&plan_path).unwrap().sets[0].action,
+            PlanAction::Delete { to_trash: true }
+        );
+    }
+
+    #[test]
+    fn test_planned_file_check() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("file.txt");
+        fs::write(&file_path, "duplicate").unwrap();
+        let planned = planned_file(&file_path);
+        assert!(planned.check().is_ok());
+
+        fs::write(&file_path, "changed contents").unwrap();
+        assert!(planned.check().is_err());
+        fs::remove_file(&file_path).unwrap();
+        assert!(planned.check().is_err());
+    }
+}
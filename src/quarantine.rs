@@ -0,0 +1,454 @@
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const MANIFEST_FILE_NAME: &str = "manifest.csv";
+
+const MANIFEST_HEADER: [&str; 8] = [
+    "Quarantined Path",
+    "Original Path",
+    "Keeper Path",
+    "Hash",
+    "Size",
+    "Created At",
+    "Modified At",
+    "Quarantined At",
+];
+
+/// # QuarantineEntry
+/// A file moved into quarantine.
+///
+/// * `quarantined_path` - Where the file is now.
+/// * `original_path` - Where the file was, and where it is restored to.
+/// * `keeper_path` - The file it duplicated, which was kept.
+/// * `hash` - The hash shared by the file and the keeper.
+/// * `size` - The size of the file in bytes.
+/// * `created_at` - When the file was created.
+/// * `modified_at` - When the file was last modified.
+/// * `quarantined_at` - When the file was moved into quarantine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuarantineEntry {
+    pub quarantined_path: String,
+    pub original_path: String,
+    pub keeper_path: String,
+    pub hash: String,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+    pub quarantined_at: DateTime<Utc>,
+}
+
+/// # QuarantineSummary
+/// What happened when restoring or purging a quarantine.
+///
+/// * `files` - The number of files restored or purged.
+/// * `size` - The total size of those files in bytes.
+/// * `errors` - A message for each file that could not be restored or purged.  They stay in the manifest.
+#[derive(Debug, Default)]
+pub struct QuarantineSummary {
+    pub files: usize,
+    pub size: u64,
+    pub errors: Vec<String>,
+}
+
+/// # Manifest
+/// The record of every file in a quarantine folder, stored as a csv file in that folder.
+///
+/// * `path` - The path of the manifest file.
+/// * `entries` - The quarantined files.
+pub struct Manifest {
+    path: PathBuf,
+    entries: Vec<QuarantineEntry>,
+}
+
+impl Manifest {
+    /// Load the manifest of a quarantine folder.  A missing manifest is an empty quarantine.
+    pub fn load(location: &Path) -> Result<Manifest, io::Error> {
+        let path = location.join(MANIFEST_FILE_NAME);
+        let mut entries = Vec::new();
+        if path.exists() {
+            let mut rdr = csv::Reader::from_path(&path)?;
+            for record in rdr.records() {
+                let record = record?;
+                let field = |index: usize| record.get(index).unwrap_or_default();
+                entries.push(QuarantineEntry {
+                    quarantined_path: field(0).to_string(),
+                    original_path: field(1).to_string(),
+                    keeper_path: field(2).to_string(),
+                    hash: field(3).to_string(),
+                    size: field(4).parse().map_err(io::Error::other)?,
+                    created_at: parse_timestamp(field(5))?,
+                    modified_at: parse_timestamp(field(6))?,
+                    quarantined_at: parse_timestamp(field(7))?,
+                });
+            }
+        }
+        Ok(Manifest { path, entries })
+    }
+
+    /// Add an entry to the manifest of a quarantine folder.  Entries are appended, so files
+    /// quarantined before an interruption are still recorded.
+    pub fn append(location: &Path, entry: &QuarantineEntry) -> Result<(), io::Error> {
+        fs::create_dir_all(location)?;
+        let path = location.join(MANIFEST_FILE_NAME);
+        let is_new = !path.exists();
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        let mut wtr = csv::Writer::from_writer(file);
+        if is_new {
+            wtr.write_record(MANIFEST_HEADER)?;
+        }
+        wtr.write_record(entry_record(entry))?;
+        wtr.flush()
+    }
+
+    /// Write the manifest to its file, replacing the file atomically.
+    pub fn save(&self) -> Result<(), io::Error> {
+        let temp_path = self.path.with_extension("csv.tmp");
+        {
+            let mut wtr = csv::Writer::from_path(&temp_path)?;
+            wtr.write_record(MANIFEST_HEADER)?;
+            for entry in &self.entries {
+                wtr.write_record(entry_record(entry))?;
+            }
+            wtr.flush()?;
+        }
+        fs::rename(&temp_path, &self.path)
+    }
+
+    /// The quarantined files.
+    pub fn entries(&self) -> &[QuarantineEntry] {
+        &self.entries
+    }
+
+    /// Remove the entry for a quarantined file.  Returns true if there was one.
+    pub fn remove(&mut self, quarantined_path: &str) -> bool {
+        let count = self.entries.len();
+        self.entries
+            .retain(|entry| entry.quarantined_path != quarantined_path);
+        self.entries.len() != count
+    }
+
+    /// Move quarantined files back to where they came from, and remove them from the manifest.
+    /// A file is never restored over one that has since been created at its original path.
+    ///
+    /// * `filter` - Only restore files whose original path matches this pattern.
+    /// * `dry_run` - Report what would be restored without moving anything.
+    pub fn restore(&mut self, filter: Option<&glob::Pattern>, dry_run: bool) -> QuarantineSummary {
+        let mut summary = QuarantineSummary::default();
+        let mut remaining = Vec::new();
+        for entry in self.entries.drain(..) {
+            if filter.is_some_and(|filter| !filter.matches(&entry.original_path)) {
+                remaining.push(entry);
+                continue;
+            }
+            let result = if Path::new(&entry.original_path).exists() {
+                Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "a file already exists at the original path",
+                ))
+            } else if dry_run {
+                Ok(())
+            } else {
+                move_file(
+                    Path::new(&entry.quarantined_path),
+                    Path::new(&entry.original_path),
+                )
+            };
+            match result {
+                Ok(()) => {
+                    summary.files += 1;
+                    summary.size += entry.size;
+                    if dry_run {
+                        remaining.push(entry);
+                        continue;
+                    }
+                    // the file is back, so a timestamp that can not be set is only reported
+                    let original_path = Path::new(&entry.original_path);
+                    if let Err(e) = set_modified_time(original_path, entry.modified_at.into()) {
+                        summary.errors.push(format!(
+                            "Restored {} but unable to set its modified time: {}",
+                            entry.original_path, e
+                        ));
+                    }
+                }
+                Err(e) => {
+                    summary
+                        .errors
+                        .push(format!("Unable to restore {}: {}", entry.original_path, e));
+                    remaining.push(entry);
+                }
+            }
+        }
+        self.entries = remaining;
+        summary
+    }
+
+    /// Permanently remove quarantined files, and remove them from the manifest.
+    ///
+    /// * `cutoff` - Only remove files quarantined before this time.
+    /// * `dry_run` - Report what would be removed without removing anything.
+    pub fn purge(&mut self, cutoff: DateTime<Utc>, dry_run: bool) -> QuarantineSummary {
+        let mut summary = QuarantineSummary::default();
+        let mut remaining = Vec::new();
+        for entry in self.entries.drain(..) {
+            if entry.quarantined_at >= cutoff {
+                remaining.push(entry);
+                continue;
+            }
+            let result = match dry_run {
+                true => Ok(()),
+                false => match fs::remove_file(&entry.quarantined_path) {
+                    // already gone, so there is nothing left to purge
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                    result => result,
+                },
+            };
+            match result {
+                Ok(()) => {
+                    summary.files += 1;
+                    summary.size += entry.size;
+                    if dry_run {
+                        remaining.push(entry);
+                    } else if let Some(parent) = Path::new(&entry.quarantined_path).parent() {
+                        // tidy up the hash folder once it is empty
+                        let _ = fs::remove_dir(parent);
+                    }
+                }
+                Err(e) => {
+                    summary
+                        .errors
+                        .push(format!("Unable to purge {}: {}", entry.quarantined_path, e));
+                    remaining.push(entry);
+                }
+            }
+        }
+        self.entries = remaining;
+        summary
+    }
+}
+
+/// # unused_path
+/// Get a path that does not exist yet, by adding a counter to the file name if needed.
+pub fn unused_path(path: &Path) -> PathBuf {
+    let mut candidate = path.to_path_buf();
+    let mut counter = 1;
+    let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    while candidate.exists() {
+        let file_name = match path.extension() {
+            Some(extension) => format!("{}_{}.{}", file_stem, counter, extension.to_string_lossy()),
+            None => format!("{}_{}", file_stem, counter),
+        };
+        candidate = path.with_file_name(file_name);
+        counter += 1;
+    }
+    candidate
+}
+
+/// # reserve_unused_path
+/// Create an empty file at a path that does not exist yet, by adding a counter to the file name if
+/// needed.  The file is created exclusively, so no other file can be using the path, and it can be
+/// replaced by renaming or copying onto it.
+pub fn reserve_unused_path(path: &Path) -> Result<PathBuf, io::Error> {
+    let mut candidate = path.to_path_buf();
+    let mut counter = 1;
+    let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    loop {
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(_) => return Ok(candidate),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let file_name = match path.extension() {
+                    Some(extension) => {
+                        format!("{}_{}.{}", file_stem, counter, extension.to_string_lossy())
+                    }
+                    None => format!("{}_{}", file_stem, counter),
+                };
+                candidate = path.with_file_name(file_name);
+                counter += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// # move_file
+/// Move a file, creating the destination folder, and falling back to copying when the destination
+/// is on another filesystem.  A copied file keeps its modified time.
+pub fn move_file(source: &Path, destination: &Path) -> Result<(), io::Error> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(source, destination) {
+        Err(e) if is_cross_device(&e) => {}
+        result => return result,
+    }
+    let modified = fs::metadata(source)?.modified()?;
+    fs::copy(source, destination)?;
+    if let Err(e) = set_modified_time(destination, modified) {
+        let _ = fs::remove_file(destination);
+        return Err(e);
+    }
+    fs::remove_file(source)
+}
+
+/// # is_cross_device
+/// True if an error is from renaming a file onto another filesystem.
+pub fn is_cross_device(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    let cross_device = libc::EXDEV;
+    // ERROR_NOT_SAME_DEVICE
+    #[cfg(windows)]
+    let cross_device = 17;
+    error.raw_os_error() == Some(cross_device)
+}
+
+/// # set_modified_time
+/// Set the modified time of a file.
+fn set_modified_time(path: &Path, modified: SystemTime) -> Result<(), io::Error> {
+    // windows needs write access to change the times, unix only needs to own the file
+    fs::OpenOptions::new()
+        .read(true)
+        .write(cfg!(windows))
+        .open(path)?
+        .set_modified(modified)
+}
+
+fn entry_record(entry: &QuarantineEntry) -> [String; 8] {
+    [
+        entry.quarantined_path.clone(),
+        entry.original_path.clone(),
+        entry.keeper_path.clone(),
+        entry.hash.clone(),
+        entry.size.to_string(),
+        entry.created_at.to_rfc3339(),
+        entry.modified_at.to_rfc3339(),
+        entry.quarantined_at.to_rfc3339(),
+    ]
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, io::Error> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn quarantine_file(location: &Path, original: &Path, age_days: i64) -> QuarantineEntry {
+        let quarantined = unused_path(&location.join("hash").join("file.txt"));
+        move_file(original, &quarantined).unwrap();
+        let entry = QuarantineEntry {
+            quarantined_path: quarantined.to_str().unwrap().to_string(),
+            original_path: original.to_str().unwrap().to_string(),
+            keeper_path: "keeper.txt".to_string(),
+            hash: "hash".to_string(),
+            size: 8,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            quarantined_at: Utc::now() - chrono::Duration::days(age_days),
+        };
+        Manifest::append(location, &entry).unwrap();
+        entry
+    }
+
+    #[test]
+    fn test_manifest_restore() {
+        let temp_dir = tempdir().unwrap();
+        let location = temp_dir.path().join("quarantine");
+        let mut originals = Vec::new();
+        for folder in ["a", "b"] {
+            let original = temp_dir.path().join(folder).join("file.txt");
+            fs::create_dir_all(original.parent().unwrap()).unwrap();
+            fs::write(&original, "contents").unwrap();
+            quarantine_file(&location, &original, 0);
+            assert!(!original.exists());
+            originals.push(original);
+        }
+
+        let mut manifest = Manifest::load(&location).unwrap();
+        assert_eq!(manifest.entries().len(), 2);
+        assert_ne!(
+            manifest.entries()[0].quarantined_path,
+            manifest.entries()[1].quarantined_path
+        );
+
+        // only restore the file from folder a
+        let filter = glob::Pattern::new(&format!("{}/a/*", temp_dir.path().display())).unwrap();
+        let summary = manifest.restore(Some(&filter), false);
+        assert_eq!(summary.files, 1);
+        assert!(summary.errors.is_empty());
+        manifest.save().unwrap();
+        assert_eq!(fs::read_to_string(&originals[0]).unwrap(), "contents");
+        assert!(!originals[1].exists());
+
+        // a file now in the way is not overwritten
+        fs::write(&originals[1], "new").unwrap();
+        let mut manifest = Manifest::load(&location).unwrap();
+        assert_eq!(manifest.entries().len(), 1);
+        let summary = manifest.restore(None, false);
+        assert_eq!(summary.files, 0);
+        assert_eq!(summary.errors.len(), 1);
+        assert_eq!(manifest.entries().len(), 1);
+        assert_eq!(fs::read_to_string(&originals[1]).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_manifest_restore_modified_time() {
+        let temp_dir = tempdir().unwrap();
+        let location = temp_dir.path().join("quarantine");
+        let original = temp_dir.path().join("file.txt");
+        fs::write(&original, "contents").unwrap();
+        let mut entry = quarantine_file(&location, &original, 0);
+        // the time the file had when it was quarantined
+        entry.modified_at = DateTime::parse_from_rfc3339("2020-01-02T03:04:05Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut manifest = Manifest {
+            path: location.join(MANIFEST_FILE_NAME),
+            entries: vec![entry.clone()],
+        };
+
+        let summary = manifest.restore(None, false);
+        assert_eq!(summary.files, 1);
+        assert!(summary.errors.is_empty());
+        let modified: DateTime<Utc> = fs::metadata(&original).unwrap().modified().unwrap().into();
+        assert_eq!(modified, entry.modified_at);
+    }
+
+    #[test]
+    fn test_manifest_purge() {
+        let temp_dir = tempdir().unwrap();
+        let location = temp_dir.path().join("quarantine");
+        let mut entries = Vec::new();
+        for (name, age_days) in [("old.txt", 40), ("new.txt", 1)] {
+            let original = temp_dir.path().join(name);
+            fs::write(&original, "contents").unwrap();
+            entries.push(quarantine_file(&location, &original, age_days));
+        }
+
+        let mut manifest = Manifest::load(&location).unwrap();
+        let cutoff = Utc::now() - chrono::Duration::days(30);
+        let summary = manifest.purge(cutoff, true);
+        assert_eq!(summary.files, 1);
+        assert_eq!(manifest.entries().len(), 2);
+        assert!(Path::new(&entries[0].quarantined_path).exists());
+
+        let summary = manifest.purge(cutoff, false);
+        assert_eq!(summary.files, 1);
+        assert_eq!(summary.size, 8);
+        assert_eq!(manifest.entries(), &entries[1..]);
+        assert!(!Path::new(&entries[0].quarantined_path).exists());
+        assert!(Path::new(&entries[1].quarantined_path).exists());
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs::File;
+use std::io;
+
+/// The most bytes asked of the kernel in one call.  Some filesystems (btrfs) cap a single request at 16MiB.
+#[cfg(target_os = "linux")]
+const MAX_DEDUPE_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// `_IOWR(0x94, 54, struct file_dedupe_range)` from `linux/fs.h`
+#[cfg(target_os = "linux")]
+const FIDEDUPERANGE: libc::c_ulong = 0xC018_9436;
+
+/// The ranges differ, reported in `FileDedupeRangeInfo::status`
+#[cfg(target_os = "linux")]
+const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+
+/// `struct file_dedupe_range` from `linux/fs.h`, with room for a single destination.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct FileDedupeRange {
+    src_offset: u64,
+    src_length: u64,
+    dest_count: u16,
+    reserved1: u16,
+    reserved2: u32,
+    info: [FileDedupeRangeInfo; 1],
+}
+
+/// `struct file_dedupe_range_info` from `linux/fs.h`
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct FileDedupeRangeInfo {
+    dest_fd: i64,
+    dest_offset: u64,
+    bytes_deduped: u64,
+    status: i32,
+    reserved: u32,
+}
+
+/// # dedupe_files
+/// Share the extents of a source file with a destination file that has the same contents, using the
+/// FIDEDUPERANGE ioctl.  The kernel compares the bytes itself, and leaves both files independent.
+///
+/// * `source` - The file to share extents from.
+/// * `destination` - The file to share extents with.  It must be opened for writing.
+/// * `length` - The number of bytes to deduplicate.
+/// * `Result<u64, io::Error>` - The number of bytes deduplicated.
+#[cfg(target_os = "linux")]
+pub fn dedupe_files(source: &File, destination: &File, length: u64) -> Result<u64, io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut offset: u64 = 0;
+    let mut deduplicated: u64 = 0;
+    while offset < length {
+        let mut range = FileDedupeRange {
+            src_offset: offset,
+            src_length: (length - offset).min(MAX_DEDUPE_LENGTH),
+            dest_count: 1,
+            reserved1: 0,
+            reserved2: 0,
+            info: [FileDedupeRangeInfo {
+                dest_fd: destination.as_raw_fd() as i64,
+                dest_offset: offset,
+                ..Default::default()
+            }],
+        };
+        // SAFETY: range is a valid file_dedupe_range with room for dest_count entries
+        let result = unsafe {
+            libc::ioctl(
+                source.as_raw_fd(),
+                FIDEDUPERANGE as _,
+                &mut range as *mut FileDedupeRange,
+            )
+        };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            return Err(match error.raw_os_error() {
+                Some(libc::EOPNOTSUPP) | Some(libc::ENOTTY) => io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "filesystem does not support reflink deduplication ({})",
+                        error
+                    ),
+                ),
+                _ => error,
+            });
+        }
+
+        let info = &range.info[0];
+        if info.status == FILE_DEDUPE_RANGE_DIFFERS {
+            return Err(io::Error::other("file contents differ"));
+        }
+        if info.status < 0 {
+            return Err(io::Error::from_raw_os_error(-info.status));
+        }
+        // the kernel made no progress, so the rest of the file would never be deduplicated
+        if info.bytes_deduped == 0 {
+            return Err(io::Error::other(format!(
+                "only {} of {} bytes could be deduplicated",
+                offset, length
+            )));
+        }
+        offset += info.bytes_deduped;
+        deduplicated += info.bytes_deduped;
+    }
+    Ok(deduplicated)
+}
+
+/// # dedupe_files
+/// Reflink deduplication needs the Linux FIDEDUPERANGE ioctl, so it is not available on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn dedupe_files(_source: &File, _destination: &File, _length: u64) -> Result<u64, io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflink deduplication is only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_dedupe_files() {
+        let temp_dir = tempdir().unwrap();
+        let source_path = temp_dir.path().join("source.bin");
+        let destination_path = temp_dir.path().join("destination.bin");
+        let contents = vec![7u8; 64 * 1024];
+        std::fs::write(&source_path, &contents).unwrap();
+        std::fs::write(&destination_path, &contents).unwrap();
+
+        let source = File::open(&source_path).unwrap();
+        let destination = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&destination_path)
+            .unwrap();
+        // the temporary folder may or may not be on a filesystem with reflink support
+        match dedupe_files(&source, &destination, contents.len() as u64) {
+            Ok(deduplicated) => assert_eq!(deduplicated, contents.len() as u64),
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::Unsupported),
+        }
+        // either way the contents are untouched
+        assert_eq!(std::fs::read(&destination_path).unwrap(), contents);
+    }
+}
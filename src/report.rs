@@ -0,0 +1,486 @@
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::hasher::HashAlgorithm;
+
+/// # ReportFormat
+/// The format of the report file.
+///
+/// * `Csv` - One row for each duplicate file.
+/// * `Json` - A single document with the run metadata, the options used and every duplicate set.
+/// * `Ndjson` - One duplicate set per line, so the report can be streamed.
+/// * `Html` - A single static page, with a table of the duplicate sets that can be sorted and expanded.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    #[default]
+    Csv,
+    Json,
+    Ndjson,
+    Html,
+}
+
+/// The styles of the html report, kept inline so the page needs nothing else.
+const HTML_STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { margin-bottom: 0.2em; }
+dl.summary { display: grid; grid-template-columns: max-content auto; gap: 0.3em 1.5em; }
+dl.summary dt { font-weight: bold; }
+dl.summary dd { margin: 0; }
+table { border-collapse: collapse; width: 100%; margin-top: 1.5em; }
+th, td { text-align: left; padding: 0.4em 0.6em; border-bottom: 1px solid #ddd; }
+th { background: #f3f3f3; cursor: pointer; user-select: none; }
+th[data-order="asc"]::after { content: " \25B2"; }
+th[data-order="desc"]::after { content: " \25BC"; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+tr.set { cursor: pointer; }
+tr.set:hover { background: #f7f9ff; }
+tr.files > td { background: #fafafa; padding-left: 2em; }
+tr.files table { margin-top: 0; }
+.hash { font-family: monospace; }
+.keeper { color: #1a7f37; font-weight: bold; }
+.error { color: #cf222e; }
+"#;
+
+/// The script of the html report, which sorts the sets by a column and shows the files of a set when it is clicked.
+const HTML_SCRIPT: &str = r#"
+document.querySelectorAll("th[data-key]").forEach(function (th) {
+  th.addEventListener("click", function () {
+    var key = th.dataset.key;
+    var ascending = th.dataset.order !== "asc";
+    document.querySelectorAll("th[data-key]").forEach(function (other) { delete other.dataset.order; });
+    th.dataset.order = ascending ? "asc" : "desc";
+    var table = th.closest("table");
+    var sets = Array.prototype.slice.call(table.querySelectorAll(":scope > tbody"));
+    sets.sort(function (a, b) {
+      var x = a.dataset[key], y = b.dataset[key];
+      var order = th.dataset.numeric ? Number(x) - Number(y) : x.localeCompare(y);
+      return ascending ? order : -order;
+    });
+    sets.forEach(function (set) { table.appendChild(set); });
+  });
+});
+document.querySelectorAll("tr.set").forEach(function (row) {
+  row.addEventListener("click", function () {
+    row.nextElementSibling.hidden = !row.nextElementSibling.hidden;
+  });
+});
+"#;
+
+/// # RunMetadata
+/// Details of the run that wrote a report.
+///
+/// * `tool` - The name of the tool.
+/// * `version` - The version of the tool.
+/// * `command` - The command that was run.
+/// * `created_at` - When the report was written.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub tool: String,
+    pub version: String,
+    pub command: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// # ReportOptions
+/// The options that affect which files were found and how they were compared.
+///
+/// * `path` - The folder that was searched.
+/// * `wildcard` - The pattern files had to match.
+/// * `exclusion_wildcard` - The pattern of files that were excluded.
+/// * `recursive` - Sub folders were searched.
+/// * `include_empty_files` - Empty files were included.
+/// * `include_hidden_files` - Hidden files were included.
+/// * `dry_run` - No files were changed.
+/// * `hash_algorithm` - The algorithm used to hash files.
+/// * `normalize_line_endings` - CRLF and LF line endings were treated as equal.
+/// * `verify` - Duplicates were compared byte-for-byte with the keeper.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportOptions {
+    pub path: String,
+    pub wildcard: String,
+    pub exclusion_wildcard: String,
+    pub recursive: bool,
+    pub include_empty_files: bool,
+    pub include_hidden_files: bool,
+    pub dry_run: bool,
+    pub hash_algorithm: HashAlgorithm,
+    pub normalize_line_endings: bool,
+    pub verify: bool,
+}
+
+/// # ReportFile
+/// A file in a duplicate set.
+///
+/// * `path` - The path of the file.
+/// * `size` - The size of the file in bytes.
+/// * `created_at` - When the file was created.
+/// * `modified_at` - When the file was last modified.
+/// * `hardlinks` - Other paths that are hardlinks to the same file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportFile {
+    pub path: String,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hardlinks: Vec<String>,
+}
+
+/// # ReportOutcome
+/// What happened to a duplicate, or one of its hardlinks, when it was processed.
+///
+/// * `path` - The path of the file.
+/// * `destination` - Where the file went, or the file it was linked to.
+/// * `error_kind` - The kind of error, if the operation failed.
+/// * `error` - Why the operation failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportOutcome {
+    pub path: String,
+    pub destination: Option<String>,
+    pub error_kind: Option<String>,
+    pub error: Option<String>,
+}
+
+/// # ReportSet
+/// A set of duplicate files and what happened to them.
+///
+/// * `hash` - The hash shared by the files.
+/// * `algorithm` - The algorithm used to calculate the hash.
+/// * `keeper` - The file kept, if one was selected.
+/// * `extras` - The duplicates of the keeper.
+/// * `result` - What happened to the duplicates.
+/// * `normalized_match` - The files only match once line endings are normalized.
+/// * `outcomes` - What happened to each duplicate that was processed.
+/// * `reason` - Why the file kept was changed from the one selected, or why the duplicates were left alone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportSet {
+    pub hash: String,
+    pub algorithm: HashAlgorithm,
+    pub keeper: Option<ReportFile>,
+    pub extras: Vec<ReportFile>,
+    pub result: String,
+    pub normalized_match: bool,
+    #[serde(default)]
+    pub outcomes: Vec<ReportOutcome>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl ReportSet {
+    /// The bytes taken up by the duplicates, which removing them would free.  When no file was kept,
+    /// one of the copies would still be.
+    pub fn wasted_bytes(&self) -> u64 {
+        let total: u64 = self.extras.iter().map(|extra| extra.size).sum();
+        match (&self.keeper, self.extras.first()) {
+            (None, Some(first)) => total - first.size,
+            _ => total,
+        }
+    }
+
+    /// What happened to a file of the set, if it was processed.
+    pub fn outcome(&self, path: &str) -> Option<&ReportOutcome> {
+        self.outcomes.iter().find(|outcome| outcome.path == path)
+    }
+
+    /// The files the action failed on.
+    pub fn failures(&self) -> impl Iterator<Item = &ReportOutcome> {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.error.is_some())
+    }
+}
+
+/// # Report
+/// A structured report of a run.
+///
+/// * `metadata` - Details of the run.
+/// * `options` - The options used.
+/// * `sets` - The duplicate sets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub metadata: RunMetadata,
+    pub options: ReportOptions,
+    pub sets: Vec<ReportSet>,
+}
+
+impl Report {
+    /// Write the report as a single JSON document.
+    pub fn write_json(&self, path: &Path) -> Result<(), io::Error> {
+        let contents = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+
+    /// Write the report as a single static html page, with summary statistics and a table of the
+    /// duplicate sets ordered by the bytes they waste.  Clicking a set shows the keeper and its duplicates.
+    pub fn write_html(&self, path: &Path) -> Result<(), io::Error> {
+        fs::write(path, self.to_html())
+    }
+
+    /// Render the report as html.
+    fn to_html(&self) -> String {
+        let mut sets: Vec<&ReportSet> = self.sets.iter().collect();
+        sets.sort_by_key(|set| std::cmp::Reverse(set.wasted_bytes()));
+        // files that did not match byte-for-byte are not duplicates, so they are not counted
+        let duplicate_sets: Vec<&ReportSet> = self
+            .sets
+            .iter()
+            .filter(|set| set.result != "Mismatched")
+            .collect();
+        let duplicate_files: usize = duplicate_sets.iter().map(|set| set.extras.len()).sum();
+        let wasted_bytes: u64 = duplicate_sets.iter().map(|set| set.wasted_bytes()).sum();
+        let failures: Vec<&ReportOutcome> =
+            self.sets.iter().flat_map(|set| set.failures()).collect();
+
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!(
+            "<title>{} report</title>\n<style>{}</style>\n</head>\n<body>\n",
+            escape_html(&self.metadata.tool),
+            HTML_STYLE
+        ));
+        html.push_str(&format!(
+            "<h1>{} report</h1>\n<dl class=\"summary\">\n",
+            escape_html(&self.metadata.tool)
+        ));
+        let summary = [
+            ("Created", self.metadata.created_at.to_rfc2822()),
+            (
+                "Command",
+                format!("{} {}", self.metadata.tool, self.metadata.command),
+            ),
+            ("Version", self.metadata.version.clone()),
+            ("Path", self.options.path.clone()),
+            ("Hash algorithm", self.options.hash_algorithm.to_string()),
+            ("Dry run", self.options.dry_run.to_string()),
+            ("Duplicate sets", duplicate_sets.len().to_string()),
+            ("Duplicate files", duplicate_files.to_string()),
+            ("Wasted space", bytesize::ByteSize(wasted_bytes).to_string()),
+            ("Failures", failures.len().to_string()),
+        ];
+        for (name, value) in summary {
+            html.push_str(&format!(
+                "<dt>{}</dt><dd>{}</dd>\n",
+                name,
+                escape_html(&value)
+            ));
+        }
+        html.push_str("</dl>\n");
+
+        if !failures.is_empty() {
+            html.push_str("<h2>Failures</h2>\n<ul class=\"failures\">\n");
+            for failure in &failures {
+                html.push_str(&format!(
+                    "<li>{}: <span class=\"error\">{}</span></li>\n",
+                    escape_html(&failure.path),
+                    escape_html(failure.error.as_deref().unwrap_or_default())
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        html.push_str("<table class=\"sets\">\n<thead><tr>");
+        html.push_str("<th data-key=\"hash\">Hash</th>");
+        html.push_str("<th data-key=\"keeper\">Keeper</th>");
+        html.push_str("<th data-key=\"files\" data-numeric=\"true\">Duplicates</th>");
+        html.push_str("<th data-key=\"size\" data-numeric=\"true\">File size</th>");
+        html.push_str(
+            "<th data-key=\"wasted\" data-numeric=\"true\" data-order=\"desc\">Wasted</th>",
+        );
+        html.push_str("<th data-key=\"result\">Result</th>");
+        html.push_str("</tr></thead>\n");
+        for set in sets {
+            let keeper_path = set
+                .keeper
+                .as_ref()
+                .map(|keeper| keeper.path.as_str())
+                .unwrap_or_default();
+            let size = set
+                .keeper
+                .as_ref()
+                .or(set.extras.first())
+                .map(|file| file.size)
+                .unwrap_or_default();
+            let result = match (&set.reason, set.normalized_match) {
+                (Some(reason), _) => format!("{} ({})", set.result, reason),
+                (None, true) => format!("{} (normalized match)", set.result),
+                (None, false) => set.result.clone(),
+            };
+            html.push_str(&format!(
+                "<tbody data-hash=\"{}\" data-keeper=\"{}\" data-files=\"{}\" data-size=\"{}\" data-wasted=\"{}\" data-result=\"{}\">\n",
+                escape_html(&set.hash),
+                escape_html(keeper_path),
+                set.extras.len(),
+                size,
+                set.wasted_bytes(),
+                escape_html(&result)
+            ));
+            html.push_str(&format!(
+                "<tr class=\"set\"><td class=\"hash\">{}</td><td>{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td>{}</td></tr>\n",
+                escape_html(&set.hash),
+                escape_html(keeper_path),
+                set.extras.len(),
+                bytesize::ByteSize(size),
+                bytesize::ByteSize(set.wasted_bytes()),
+                escape_html(&result)
+            ));
+            html.push_str("<tr class=\"files\" hidden><td colspan=\"6\"><table>\n");
+            html.push_str("<tr><th>Role</th><th>Path</th><th>Size</th><th>Created</th><th>Modified</th><th>Outcome</th></tr>\n");
+            let files = set
+                .keeper
+                .iter()
+                .map(|keeper| ("keeper", keeper))
+                .chain(set.extras.iter().map(|extra| ("duplicate", extra)));
+            for (role, file) in files {
+                let mut path = escape_html(&file.path);
+                for link in &file.hardlinks {
+                    path.push_str(&format!("<br>already linked: {}", escape_html(link)));
+                }
+                let outcome = match set.outcome(&file.path) {
+                    Some(ReportOutcome {
+                        error: Some(error), ..
+                    }) => format!("<span class=\"error\">{}</span>", escape_html(error)),
+                    Some(ReportOutcome {
+                        destination: Some(destination),
+                        ..
+                    }) => format!("&rarr; {}", escape_html(destination)),
+                    _ => String::new(),
+                };
+                html.push_str(&format!(
+                    "<tr><td class=\"{}\">{}</td><td>{}</td><td class=\"number\">{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    role,
+                    role,
+                    path,
+                    bytesize::ByteSize(file.size),
+                    file.created_at.to_rfc2822(),
+                    file.modified_at.to_rfc2822(),
+                    outcome
+                ));
+            }
+            html.push_str("</table></td></tr>\n</tbody>\n");
+        }
+        html.push_str("</table>\n");
+        html.push_str(&format!("<script>{}</script>\n", HTML_SCRIPT));
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    /// Write the report as newline delimited JSON, one duplicate set per line.
+    pub fn write_ndjson(&self, path: &Path) -> Result<(), io::Error> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        for set in &self.sets {
+            serde_json::to_writer(&mut writer, set).map_err(io::Error::other)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()
+    }
+}
+
+/// # escape_html
+/// Escape text for use in html content and attribute values.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A file found now, with no hardlinks.
+    pub(crate) fn report_file(path: &str, size: u64) -> ReportFile {
+        ReportFile {
+            path: path.to_string(),
+            size,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            hardlinks: vec![],
+        }
+    }
+
+    /// A report of a search of /data made now by a command.
+    pub(crate) fn report(command: &str, sets: Vec<ReportSet>) -> Report {
+        Report {
+            metadata: RunMetadata {
+                tool: "dupefindr".to_string(),
+                version: "1.0.0".to_string(),
+                command: command.to_string(),
+                created_at: Utc::now(),
+            },
+            options: ReportOptions {
+                path: "/data".to_string(),
+                wildcard: "*".to_string(),
+                exclusion_wildcard: "".to_string(),
+                recursive: true,
+                include_empty_files: false,
+                include_hidden_files: false,
+                dry_run: true,
+                hash_algorithm: HashAlgorithm::Md5,
+                normalize_line_endings: false,
+                verify: true,
+            },
+            sets,
+        }
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_to_html() {
+        let set = |hash: &str, size: u64, extras: usize| ReportSet {
+            hash: hash.to_string(),
+            algorithm: HashAlgorithm::Md5,
+            keeper: Some(report_file(&format!("/data/{}/keep.txt", hash), size)),
+            extras: (0..extras)
+                .map(|index| report_file(&format!("/data/{}/<copy{}>.txt", hash, index), size))
+                .collect(),
+            result: "Found".to_string(),
+            normalized_match: false,
+            outcomes: vec![],
+            reason: None,
+        };
+        let mut report = report("find", vec![set("small", 10, 1), set("large", 1000, 2)]);
+        report.sets[0].outcomes.push(ReportOutcome {
+            path: "/data/small/<copy0>.txt".to_string(),
+            destination: None,
+            error_kind: Some("PermissionDenied".to_string()),
+            error: Some("Permission denied".to_string()),
+        });
+        let html = report.to_html();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        // no external assets
+        assert!(!html.contains("src="));
+        assert!(!html.contains("<link"));
+        // the set that wastes the most comes first
+        let large = html.find("data-hash=\"large\"").unwrap();
+        let small = html.find("data-hash=\"small\"").unwrap();
+        assert!(large < small);
+        assert!(html.contains("data-wasted=\"2000\""));
+        assert!(html.contains("/data/large/keep.txt"));
+        assert!(html.contains("/data/large/&lt;copy1&gt;.txt"));
+        // failures are listed
+        assert!(html.contains("<dt>Failures</dt><dd>1</dd>"));
+        assert!(html.contains(
+            "<li>/data/small/&lt;copy0&gt;.txt: <span class=\"error\">Permission denied</span></li>"
+        ));
+    }
+}
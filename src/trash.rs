@@ -0,0 +1,312 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{FileOperations, RealFileOperations};
+
+/// # TrashFileOperations
+/// Implementation of `FileOperations` that moves removed files to the trash, following the
+/// freedesktop.org trash specification, so they can be restored from a desktop file manager.
+/// Files on the same filesystem as the home trash go to `$XDG_DATA_HOME/Trash`, other files go to the
+/// trash at the top of their own filesystem.  Every other operation is the same as `RealFileOperations`.
+///
+/// * `home_trash` - The path of the home trash.
+pub struct TrashFileOperations {
+    home_trash: PathBuf,
+}
+
+impl TrashFileOperations {
+    /// Create a trash that uses a home trash folder.
+    pub fn new(home_trash: &Path) -> TrashFileOperations {
+        TrashFileOperations {
+            home_trash: home_trash.to_path_buf(),
+        }
+    }
+
+    /// Move a file into a trash folder, writing its `.trashinfo` first so the file is never in the
+    /// trash without it.
+    ///
+    /// * `source` - The absolute path of the file.
+    /// * `trash` - The trash folder, containing the `files` and `info` folders.
+    /// * `top_dir` - The top of the filesystem the trash is on, if it is not the home trash.
+    ///   Paths in the `.trashinfo` are relative to it.
+    ///
+    /// Returns where the file is in the trash.
+    fn trash_file(
+        &self,
+        source: &Path,
+        trash: &Path,
+        top_dir: Option<&Path>,
+    ) -> Result<PathBuf, io::Error> {
+        let files_dir = trash.join("files");
+        let info_dir = trash.join("info");
+        create_private_dir(&files_dir)?;
+        create_private_dir(&info_dir)?;
+
+        let original_path = match top_dir {
+            Some(top_dir) => source.strip_prefix(top_dir).unwrap_or(source),
+            None => source,
+        };
+        let contents = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_trash_path(original_path),
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+        );
+
+        // claim a name in the trash by creating its info file
+        // a name already used in files is also taken, so an orphaned file is never overwritten
+        let file_name = source
+            .file_name()
+            .ok_or_else(|| io::Error::other(format!("Invalid file: {}", source.display())))?
+            .to_string_lossy()
+            .to_string();
+        let mut counter = 1;
+        let mut trash_name = file_name.clone();
+        let info_path = loop {
+            let info_path = info_dir.join(format!("{}.trashinfo", trash_name));
+            let claimed = if fs::symlink_metadata(files_dir.join(&trash_name)).is_ok() {
+                Err(io::Error::from(io::ErrorKind::AlreadyExists))
+            } else {
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&info_path)
+            };
+            match claimed {
+                Ok(mut info_file) => {
+                    if let Err(e) = info_file.write_all(contents.as_bytes()) {
+                        let _ = fs::remove_file(&info_path);
+                        return Err(e);
+                    }
+                    break info_path;
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    let path = Path::new(&file_name);
+                    let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                    trash_name = match path.extension() {
+                        Some(extension) => {
+                            format!("{}_{}.{}", file_stem, counter, extension.to_string_lossy())
+                        }
+                        None => format!("{}_{}", file_stem, counter),
+                    };
+                    counter += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let trashed = files_dir.join(&trash_name);
+        if let Err(e) = fs::rename(source, &trashed) {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+        Ok(trashed)
+    }
+
+    /// Find the trash for a file.  Returns the trash folder and, for a trash at the top of another
+    /// filesystem, the top of that filesystem.
+    #[cfg(unix)]
+    fn trash_for(&self, source: &Path) -> Result<(PathBuf, Option<PathBuf>), io::Error> {
+        use std::os::unix::fs::MetadataExt;
+        use std::os::unix::fs::PermissionsExt;
+
+        create_private_dir(&self.home_trash)?;
+        let device = fs::symlink_metadata(source)?.dev();
+        if fs::metadata(&self.home_trash)?.dev() == device {
+            return Ok((self.home_trash.clone(), None));
+        }
+
+        // the top of the filesystem is the last folder on the same device
+        let mut top_dir = source.parent().unwrap_or(source).to_path_buf();
+        while let Some(parent) = top_dir.parent() {
+            if fs::metadata(parent)?.dev() != device {
+                break;
+            }
+            top_dir = parent.to_path_buf();
+        }
+
+        // use $topdir/.Trash/$uid if the administrator has set up a shared trash, otherwise $topdir/.Trash-$uid
+        // SAFETY: getuid has no preconditions and can not fail
+        let uid = unsafe { libc::getuid() };
+        let shared_trash = top_dir.join(".Trash");
+        if let Ok(metadata) = fs::symlink_metadata(&shared_trash) {
+            let sticky = metadata.permissions().mode() & 0o1000 != 0;
+            if metadata.is_dir() && sticky {
+                return Ok((shared_trash.join(uid.to_string()), Some(top_dir)));
+            }
+        }
+        Ok((top_dir.join(format!(".Trash-{}", uid)), Some(top_dir)))
+    }
+
+    /// Find the trash for a file.  Only the home trash is used on this platform.
+    #[cfg(not(unix))]
+    fn trash_for(&self, _source: &Path) -> Result<(PathBuf, Option<PathBuf>), io::Error> {
+        Ok((self.home_trash.clone(), None))
+    }
+}
+
+impl FileOperations for TrashFileOperations {
+    fn copy(&self, source: &str, destination: &str, overwrite: bool) -> Result<String, io::Error> {
+        RealFileOperations.copy(source, destination, overwrite)
+    }
+    fn remove_file(&self, source: &str) -> Result<Option<String>, io::Error> {
+        let source = std::path::absolute(source)?;
+        let (trash, top_dir) = self.trash_for(&source)?;
+        self.trash_file(&source, &trash, top_dir.as_deref())
+            .map(|trashed| Some(trashed.to_string_lossy().to_string()))
+    }
+    fn rename(
+        &self,
+        source: &str,
+        destination: &str,
+        overwrite: bool,
+    ) -> Result<String, io::Error> {
+        RealFileOperations.rename(source, destination, overwrite)
+    }
+    fn hard_link(&self, source: &str, destination: &str) -> Result<(), io::Error> {
+        RealFileOperations.hard_link(source, destination)
+    }
+    fn symlink(&self, source: &str, destination: &str, relative: bool) -> Result<(), io::Error> {
+        RealFileOperations.symlink(source, destination, relative)
+    }
+    fn reflink(&self, source: &str, destination: &str) -> Result<u64, io::Error> {
+        RealFileOperations.reflink(source, destination)
+    }
+}
+
+/// # default_home_trash
+/// The location of the home trash.
+/// `$XDG_DATA_HOME/Trash`, falling back to `~/.local/share/Trash`.
+pub fn default_home_trash() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })
+        .map(|dir| dir.join("Trash"))
+}
+
+/// # create_private_dir
+/// Create a folder, and its parents, that only the current user can access.
+fn create_private_dir(path: &Path) -> Result<(), io::Error> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(path)
+}
+
+/// # encode_trash_path
+/// Percent-encode a path for the `Path` key of a `.trashinfo` file, as the specification requires.
+fn encode_trash_path(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy().as_bytes().to_vec();
+
+    let mut encoded = String::new();
+    for byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_encode_trash_path() {
+        assert_eq!(
+            encode_trash_path(Path::new("/home/user/my file#1.txt")),
+            "/home/user/my%20file%231.txt"
+        );
+    }
+
+    #[test]
+    fn test_trash_remove_file() {
+        let temp_dir = tempdir().unwrap();
+        let home_trash = temp_dir.path().join("Trash");
+        let trash = TrashFileOperations::new(&home_trash);
+        // two files with the same name
+        for folder in ["a", "b"] {
+            let folder = temp_dir.path().join(folder);
+            fs::create_dir(&folder).unwrap();
+            let file_path = folder.join("file.txt");
+            fs::write(&file_path, folder.to_str().unwrap()).unwrap();
+            trash.remove_file(file_path.to_str().unwrap()).unwrap();
+            assert!(!file_path.exists());
+        }
+
+        let files_dir = home_trash.join("files");
+        let info_dir = home_trash.join("info");
+        assert!(files_dir.join("file.txt").exists());
+        assert!(files_dir.join("file_1.txt").exists());
+        let info = fs::read_to_string(info_dir.join("file_1.txt.trashinfo")).unwrap();
+        let mut lines = info.lines();
+        assert_eq!(lines.next(), Some("[Trash Info]"));
+        assert_eq!(
+            lines.next().unwrap(),
+            format!(
+                "Path={}",
+                encode_trash_path(&temp_dir.path().join("b").join("file.txt"))
+            )
+        );
+        assert!(lines.next().unwrap().starts_with("DeletionDate="));
+    }
+
+    #[test]
+    fn test_trash_remove_file_orphaned_name() {
+        let temp_dir = tempdir().unwrap();
+        let home_trash = temp_dir.path().join("Trash");
+        let trash = TrashFileOperations::new(&home_trash);
+        // a file left in the trash without an info file
+        let files_dir = home_trash.join("files");
+        fs::create_dir_all(&files_dir).unwrap();
+        fs::write(files_dir.join("file.txt"), "orphan").unwrap();
+
+        let file_path = temp_dir.path().join("file.txt");
+        fs::write(&file_path, "trashed").unwrap();
+        trash.remove_file(file_path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(files_dir.join("file.txt")).unwrap(),
+            "orphan"
+        );
+        assert_eq!(
+            fs::read_to_string(files_dir.join("file_1.txt")).unwrap(),
+            "trashed"
+        );
+        assert!(!home_trash.join("info").join("file.txt.trashinfo").exists());
+        assert!(home_trash
+            .join("info")
+            .join("file_1.txt.trashinfo")
+            .exists());
+    }
+
+    #[test]
+    fn test_trash_remove_file_missing() {
+        let temp_dir = tempdir().unwrap();
+        let home_trash = temp_dir.path().join("Trash");
+        let trash = TrashFileOperations::new(&home_trash);
+        let file_path = temp_dir.path().join("missing.txt");
+        assert!(trash.remove_file(file_path.to_str().unwrap()).is_err());
+        // no info file is left behind
+        assert!(!home_trash
+            .join("info")
+            .join("missing.txt.trashinfo")
+            .exists());
+    }
+}
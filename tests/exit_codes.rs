@@ -0,0 +1,194 @@
+//! Runs the dupefindr binary and checks the status it exits with.
+
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+/// Run dupefindr with its config, state, cache and trash kept inside a folder, and return the exit status.
+fn run(home: &Path, args: &[&str]) -> i32 {
+    let output = Command::new(env!("CARGO_BIN_EXE_dupefindr"))
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_STATE_HOME", home.join("state"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .output()
+        .expect("failed to run dupefindr");
+    output
+        .status
+        .code()
+        .expect("dupefindr was terminated by a signal")
+}
+
+/// Create a folder with files, returning its path.
+fn create_files(root: &Path, files: &[(&str, &str)]) -> String {
+    let folder = root.join("search");
+    std::fs::create_dir_all(&folder).unwrap();
+    for (name, contents) in files {
+        std::fs::write(folder.join(name), contents).unwrap();
+    }
+    folder.to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_exit_code_no_duplicates() {
+    let temp_dir = tempdir().unwrap();
+    let folder = create_files(temp_dir.path(), &[("a.txt", "one"), ("b.txt", "two")]);
+    let code = run(
+        temp_dir.path(),
+        &["--quiet", "--no-cache", "--path", &folder, "delete"],
+    );
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn test_exit_code_duplicates_found() {
+    let temp_dir = tempdir().unwrap();
+    let folder = create_files(temp_dir.path(), &[("a.txt", "same"), ("b.txt", "same")]);
+    let code = run(
+        temp_dir.path(),
+        &[
+            "--quiet",
+            "--no-cache",
+            "--dry-run",
+            "--path",
+            &folder,
+            "delete",
+        ],
+    );
+    assert_eq!(code, 1);
+    assert!(Path::new(&folder).join("a.txt").exists());
+    assert!(Path::new(&folder).join("b.txt").exists());
+}
+
+#[test]
+fn test_exit_code_many_duplicate_sets() {
+    // 256 sets used to wrap around to a status of 0
+    let temp_dir = tempdir().unwrap();
+    let contents: Vec<String> = (0..256).map(|index| format!("set {}", index)).collect();
+    let names: Vec<(String, String)> = (0..256)
+        .flat_map(|index| {
+            [
+                (format!("{}a.txt", index), contents[index].clone()),
+                (format!("{}b.txt", index), contents[index].clone()),
+            ]
+        })
+        .collect();
+    let files: Vec<(&str, &str)> = names
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_str()))
+        .collect();
+    let folder = create_files(temp_dir.path(), &files);
+    let code = run(
+        temp_dir.path(),
+        &[
+            "--quiet",
+            "--no-cache",
+            "--dry-run",
+            "--path",
+            &folder,
+            "delete",
+        ],
+    );
+    assert_eq!(code, 1);
+}
+
+#[test]
+fn test_exit_code_partial_failure() {
+    let temp_dir = tempdir().unwrap();
+    let folder = create_files(temp_dir.path(), &[("a.txt", "same"), ("b.txt", "same")]);
+    // moving into a folder that does not exist fails
+    let missing = temp_dir.path().join("missing");
+    let code = run(
+        temp_dir.path(),
+        &[
+            "--quiet",
+            "--no-cache",
+            "--no-journal",
+            "--path",
+            &folder,
+            "move",
+            "--location",
+            missing.to_str().unwrap(),
+        ],
+    );
+    assert_eq!(code, 2);
+}
+
+#[test]
+fn test_exit_code_usage_error() {
+    let temp_dir = tempdir().unwrap();
+    assert_eq!(run(temp_dir.path(), &["--no-such-option", "find"]), 3);
+    assert_eq!(run(temp_dir.path(), &["--quiet"]), 3);
+    // help is not an error
+    assert_eq!(run(temp_dir.path(), &["--help"]), 0);
+}
+
+#[test]
+fn test_exit_code_invalid_config() {
+    let temp_dir = tempdir().unwrap();
+    let folder = create_files(temp_dir.path(), &[("a.txt", "one")]);
+    let config_dir = temp_dir.path().join("config").join("dupefindr");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    let config = config_dir.join("config.toml");
+    let args = ["--quiet", "--no-cache", "--path", &folder, "find"];
+
+    // an invalid wildcard and an unknown setting are usage errors
+    std::fs::write(&config, "protect = [\"[\"]\n").unwrap();
+    assert_eq!(run(temp_dir.path(), &args), 3);
+    std::fs::write(&config, "unknown = true\n").unwrap();
+    assert_eq!(run(temp_dir.path(), &args), 3);
+    // a config file that can not be read is not
+    std::fs::remove_file(&config).unwrap();
+    std::fs::create_dir(&config).unwrap();
+    assert_eq!(run(temp_dir.path(), &args), 4);
+}
+
+#[test]
+fn test_exit_code_invalid_restore_filter() {
+    let temp_dir = tempdir().unwrap();
+    let location = temp_dir.path().join("quarantine");
+    std::fs::create_dir(&location).unwrap();
+    let code = run(
+        temp_dir.path(),
+        &[
+            "--quiet",
+            "restore",
+            "--location",
+            location.to_str().unwrap(),
+            "--filter",
+            "[",
+        ],
+    );
+    assert_eq!(code, 3);
+}
+
+#[test]
+fn test_exit_code_io_error() {
+    let temp_dir = tempdir().unwrap();
+    let missing = temp_dir.path().join("missing");
+    let code = run(
+        temp_dir.path(),
+        &[
+            "--quiet",
+            "--no-cache",
+            "--path",
+            missing.to_str().unwrap(),
+            "find",
+        ],
+    );
+    assert_eq!(code, 4);
+}
+
+#[test]
+fn test_exit_code_find_duplicates() {
+    // find used to report no duplicates and exit 0
+    let temp_dir = tempdir().unwrap();
+    let folder = create_files(temp_dir.path(), &[("a.txt", "same"), ("b.txt", "same")]);
+    let code = run(
+        temp_dir.path(),
+        &["--quiet", "--no-cache", "--path", &folder, "find"],
+    );
+    assert_eq!(code, 1);
+}